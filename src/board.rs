@@ -0,0 +1,335 @@
+//! Physical memory maps of the PRU subsystems on supported TI SoCs.
+
+use std::fmt;
+use std::fs;
+use std::io;
+
+use crate::cores::CoreKind::{self, Pru, Rtu, TxPru};
+use crate::devicetree::DT_BASES;
+use crate::firmware::PruMemories;
+use crate::mmio::{MmioError, Result};
+use crate::sysroot::SysRoot;
+
+/// A memory or peripheral region inside a PRUSS / ICSSG instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Region {
+    /// Data RAM `n` (DRAM0 is PRU0's local RAM, DRAM1 PRU1's).
+    Dram(usize),
+    SharedRam,
+    /// Instruction RAM of a core.
+    Iram(CoreKind, usize),
+    /// Control registers of a core.
+    Ctrl(CoreKind, usize),
+    Intc,
+    Cfg,
+    Iep(usize),
+    Uart,
+    Ecap,
+}
+
+impl Region {
+    /// Parse a region name as printed by `Display` (`dram0`, `shrdram2`,
+    /// `pru1-iram`, `rtu0-ctrl`, `intc`, `iep0`, ...).
+    pub fn from_name(name: &str) -> Option<Self> {
+        let indexed = |prefix: &str| name.strip_prefix(prefix).and_then(|n| n.parse().ok());
+        match name {
+            "shrdram2" => return Some(Region::SharedRam),
+            "intc" => return Some(Region::Intc),
+            "cfg" => return Some(Region::Cfg),
+            "uart" => return Some(Region::Uart),
+            "ecap" => return Some(Region::Ecap),
+            _ => {}
+        }
+        if let Some(n) = indexed("dram") {
+            return Some(Region::Dram(n));
+        }
+        if let Some(n) = indexed("iep") {
+            return Some(Region::Iep(n));
+        }
+        let (core, what) = name.split_once('-')?;
+        let digits = core.find(|c: char| c.is_ascii_digit())?;
+        let kind = match &core[..digits] {
+            "pru" => CoreKind::Pru,
+            "rtu" => CoreKind::Rtu,
+            "txpru" => CoreKind::TxPru,
+            _ => return None,
+        };
+        let index = core[digits..].parse().ok()?;
+        match what {
+            "iram" => Some(Region::Iram(kind, index)),
+            "ctrl" => Some(Region::Ctrl(kind, index)),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Region::Dram(n) => write!(f, "dram{}", n),
+            Region::SharedRam => f.write_str("shrdram2"),
+            Region::Iram(kind, n) => write!(f, "{}{}-iram", kind.as_str(), n),
+            Region::Ctrl(kind, n) => write!(f, "{}{}-ctrl", kind.as_str(), n),
+            Region::Intc => f.write_str("intc"),
+            Region::Cfg => f.write_str("cfg"),
+            Region::Iep(n) => write!(f, "iep{}", n),
+            Region::Uart => f.write_str("uart"),
+            Region::Ecap => f.write_str("ecap"),
+        }
+    }
+}
+
+/// A region's placement relative to its PRUSS base.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegionDef {
+    pub region: Region,
+    pub offset: u64,
+    pub size: usize,
+}
+
+/// One PRUSS / ICSSG instance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pruss {
+    pub base: u64,
+    pub size: usize,
+    pub regions: &'static [RegionDef],
+}
+
+impl Pruss {
+    /// Physical address and size of `region` in this instance.
+    pub fn region(&self, region: Region) -> Option<(u64, usize)> {
+        self.regions
+            .iter()
+            .find(|r| r.region == region)
+            .map(|r| (self.base + r.offset, r.size))
+    }
+}
+
+/// Memory map of a SoC's PRU subsystems. Instances are ordered by address,
+/// matching `CoreId::icss`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SocProfile {
+    pub name: &'static str,
+    pub instances: &'static [Pruss],
+    /// Memory sizes of the largest instance, for firmware placement checks.
+    pub memories: PruMemories,
+}
+
+impl SocProfile {
+    /// Physical address and size of `region` in instance `icss`.
+    pub fn region(&self, icss: usize, region: Region) -> Option<(u64, usize)> {
+        self.instances.get(icss)?.region(region)
+    }
+}
+
+/// Supported SoC families.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Board {
+    /// AM335x (BeagleBone Black, PocketBeagle).
+    Am335x,
+    /// AM437x: PRU-ICSS1 followed by the smaller PRU-ICSS0.
+    Am437x,
+    /// AM57xx (BeagleBoard-X15, BeagleBone AI): PRU-ICSS1 and PRU-ICSS2.
+    Am57xx,
+    /// AM64x: ICSSG0 and ICSSG1.
+    Am64x,
+}
+
+impl Board {
+    pub fn profile(&self) -> &'static SocProfile {
+        match self {
+            Board::Am335x => &AM335X,
+            Board::Am437x => &AM437X,
+            Board::Am57xx => &AM57XX,
+            Board::Am64x => &AM64X,
+        }
+    }
+
+    /// Detect the running SoC from the device-tree root `compatible`.
+    pub fn detect() -> Result<Self> {
+        Board::detect_in(&SysRoot::host())
+    }
+
+    pub fn detect_in(root: &SysRoot) -> Result<Self> {
+        let mut last = io::Error::from(io::ErrorKind::NotFound);
+        for base in DT_BASES {
+            match fs::read(root.resolve(base).join("compatible")) {
+                Ok(raw) => return Board::from_compatible(&raw),
+                Err(e) => last = e,
+            }
+        }
+        Err(last.into())
+    }
+
+    /// Match a NUL-separated `compatible` list.
+    fn from_compatible(raw: &[u8]) -> Result<Self> {
+        let list = String::from_utf8_lossy(raw);
+        for compat in list.split('\0') {
+            let board = match compat {
+                c if c.starts_with("ti,am33") => Board::Am335x,
+                c if c.starts_with("ti,am43") => Board::Am437x,
+                c if c.starts_with("ti,am57") || c.starts_with("ti,dra7") => Board::Am57xx,
+                c if c.starts_with("ti,am64") => Board::Am64x,
+                _ => continue,
+            };
+            return Ok(board);
+        }
+        Err(MmioError::UnsupportedBoard(list.trim_end_matches('\0').replace('\0', " ")))
+    }
+}
+
+const fn def(region: Region, offset: u64, size: usize) -> RegionDef {
+    RegionDef { region, offset, size }
+}
+
+/// AM335x PRU-ICSS.
+const AM335X_REGIONS: &[RegionDef] = &[
+    def(Region::Dram(0), 0x0_0000, 0x2000),
+    def(Region::Dram(1), 0x0_2000, 0x2000),
+    def(Region::SharedRam, 0x1_0000, 0x3000),
+    def(Region::Intc, 0x2_0000, 0x2000),
+    def(Region::Ctrl(Pru, 0), 0x2_2000, 0x400),
+    def(Region::Ctrl(Pru, 1), 0x2_4000, 0x400),
+    def(Region::Cfg, 0x2_6000, 0x2000),
+    def(Region::Uart, 0x2_8000, 0x38),
+    def(Region::Iep(0), 0x2_e000, 0x31c),
+    def(Region::Ecap, 0x3_0000, 0x60),
+    def(Region::Iram(Pru, 0), 0x3_4000, 0x2000),
+    def(Region::Iram(Pru, 1), 0x3_8000, 0x2000),
+];
+
+/// AM437x PRU-ICSS1 and AM57xx PRU-ICSS: 32 KiB shared RAM, 12 KiB IRAM.
+const AM57XX_REGIONS: &[RegionDef] = &[
+    def(Region::Dram(0), 0x0_0000, 0x2000),
+    def(Region::Dram(1), 0x0_2000, 0x2000),
+    def(Region::SharedRam, 0x1_0000, 0x8000),
+    def(Region::Intc, 0x2_0000, 0x2000),
+    def(Region::Ctrl(Pru, 0), 0x2_2000, 0x400),
+    def(Region::Ctrl(Pru, 1), 0x2_4000, 0x400),
+    def(Region::Cfg, 0x2_6000, 0x2000),
+    def(Region::Uart, 0x2_8000, 0x38),
+    def(Region::Iep(0), 0x2_e000, 0x31c),
+    def(Region::Ecap, 0x3_0000, 0x60),
+    def(Region::Iram(Pru, 0), 0x3_4000, 0x3000),
+    def(Region::Iram(Pru, 1), 0x3_8000, 0x3000),
+];
+
+/// AM437x PRU-ICSS0: 4 KiB memories, no shared RAM or UART.
+const AM437X_ICSS0_REGIONS: &[RegionDef] = &[
+    def(Region::Dram(0), 0x0_0000, 0x1000),
+    def(Region::Dram(1), 0x0_2000, 0x1000),
+    def(Region::Intc, 0x2_0000, 0x2000),
+    def(Region::Ctrl(Pru, 0), 0x2_2000, 0x400),
+    def(Region::Ctrl(Pru, 1), 0x2_4000, 0x400),
+    def(Region::Cfg, 0x2_6000, 0x2000),
+    def(Region::Iep(0), 0x2_e000, 0x31c),
+    def(Region::Ecap, 0x3_0000, 0x60),
+    def(Region::Iram(Pru, 0), 0x3_4000, 0x1000),
+    def(Region::Iram(Pru, 1), 0x3_8000, 0x1000),
+];
+
+/// AM64x ICSSG: adds RTU and TX_PRU cores and a second IEP.
+const AM64X_REGIONS: &[RegionDef] = &[
+    def(Region::Dram(0), 0x0_0000, 0x2000),
+    def(Region::Dram(1), 0x0_2000, 0x2000),
+    def(Region::Iram(Rtu, 0), 0x0_4000, 0x2000),
+    def(Region::Iram(Rtu, 1), 0x0_6000, 0x2000),
+    def(Region::Iram(TxPru, 0), 0x0_a000, 0x1800),
+    def(Region::Iram(TxPru, 1), 0x0_c000, 0x1800),
+    def(Region::SharedRam, 0x1_0000, 0x1_0000),
+    def(Region::Intc, 0x2_0000, 0x2000),
+    def(Region::Ctrl(Pru, 0), 0x2_2000, 0x100),
+    def(Region::Ctrl(Rtu, 0), 0x2_3000, 0x100),
+    def(Region::Ctrl(Rtu, 1), 0x2_3800, 0x100),
+    def(Region::Ctrl(Pru, 1), 0x2_4000, 0x100),
+    def(Region::Ctrl(TxPru, 0), 0x2_5000, 0x100),
+    def(Region::Ctrl(TxPru, 1), 0x2_5800, 0x100),
+    def(Region::Cfg, 0x2_6000, 0x200),
+    def(Region::Uart, 0x2_8000, 0x40),
+    def(Region::Iep(0), 0x2_e000, 0x1000),
+    def(Region::Iep(1), 0x2_f000, 0x1000),
+    def(Region::Ecap, 0x3_0000, 0x100),
+    def(Region::Iram(Pru, 0), 0x3_4000, 0x3000),
+    def(Region::Iram(Pru, 1), 0x3_8000, 0x3000),
+];
+
+pub static AM335X: SocProfile = SocProfile {
+    name: "AM335x",
+    instances: &[Pruss { base: 0x4a30_0000, size: 0x8_0000, regions: AM335X_REGIONS }],
+    memories: PruMemories::AM335X,
+};
+
+pub static AM437X: SocProfile = SocProfile {
+    name: "AM437x",
+    instances: &[
+        Pruss { base: 0x5440_0000, size: 0x4_0000, regions: AM57XX_REGIONS },
+        Pruss { base: 0x5444_0000, size: 0x4_0000, regions: AM437X_ICSS0_REGIONS },
+    ],
+    memories: PruMemories::AM57XX,
+};
+
+pub static AM57XX: SocProfile = SocProfile {
+    name: "AM57xx",
+    instances: &[
+        Pruss { base: 0x4b20_0000, size: 0x8_0000, regions: AM57XX_REGIONS },
+        Pruss { base: 0x4b28_0000, size: 0x8_0000, regions: AM57XX_REGIONS },
+    ],
+    memories: PruMemories::AM57XX,
+};
+
+pub static AM64X: SocProfile = SocProfile {
+    name: "AM64x",
+    instances: &[
+        Pruss { base: 0x3000_0000, size: 0x8_0000, regions: AM64X_REGIONS },
+        Pruss { base: 0x3008_0000, size: 0x8_0000, regions: AM64X_REGIONS },
+    ],
+    memories: PruMemories::AM64X,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regions_fit_and_do_not_overlap() {
+        for board in [Board::Am335x, Board::Am437x, Board::Am57xx, Board::Am64x] {
+            for pruss in board.profile().instances {
+                let mut regions: Vec<_> = pruss.regions.to_vec();
+                regions.sort_by_key(|r| r.offset);
+                for pair in regions.windows(2) {
+                    assert!(pair[0].offset + pair[0].size as u64 <= pair[1].offset, "{:?}: {:?}", board, pair);
+                }
+                let last = regions.last().unwrap();
+                assert!(last.offset + last.size as u64 <= pruss.size as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn looks_up_regions() {
+        assert_eq!(AM335X.region(0, Region::Dram(1)), Some((0x4a30_2000, 0x2000)));
+        assert_eq!(AM57XX.region(1, Region::Iram(Pru, 1)), Some((0x4b2b_8000, 0x3000)));
+        assert_eq!(AM64X.region(0, Region::Ctrl(TxPru, 1)), Some((0x3002_5800, 0x100)));
+        assert_eq!(AM437X.region(1, Region::SharedRam), None);
+        assert_eq!(AM335X.region(1, Region::Dram(0)), None);
+    }
+
+    #[test]
+    fn region_names_round_trip() {
+        for r in AM64X_REGIONS.iter().map(|d| d.region) {
+            assert_eq!(Region::from_name(&r.to_string()), Some(r));
+        }
+        assert_eq!(Region::from_name("pru"), None);
+    }
+
+    #[test]
+    fn detects_from_compatible() {
+        let dir = tempfile::tempdir().unwrap();
+        let dt = dir.path().join("proc/device-tree");
+        fs::create_dir_all(&dt).unwrap();
+        fs::write(dt.join("compatible"), b"beagle,am5729-beagleboneai\0ti,am5728\0ti,dra742\0ti,dra7\0").unwrap();
+        assert_eq!(Board::detect_in(&SysRoot::new(dir.path())).unwrap(), Board::Am57xx);
+        fs::write(dt.join("compatible"), b"raspberrypi,4-model-b\0brcm,bcm2711\0").unwrap();
+        assert!(matches!(Board::detect_in(&SysRoot::new(dir.path())), Err(MmioError::UnsupportedBoard(_))));
+    }
+}
@@ -0,0 +1,357 @@
+//! Abstraction over physical memory access, so code built on `Mmio` can be
+//! exercised against an in-memory `MockBus`.
+
+use std::cell::RefCell;
+
+use crate::mmio::{Mmio, MmioError, MmioRegion, MmioRo, Result};
+
+/// Little-endian physical memory accessed at fixed widths.
+///
+/// Accesses must lie within the bus and be naturally aligned; otherwise
+/// implementations return `MmioError::OutOfRange` or `MmioError::Misaligned`.
+pub trait MemoryBus {
+    fn try_read_u8(&self, addr: u64) -> Result<u8>;
+    fn try_read_u16(&self, addr: u64) -> Result<u16>;
+    fn try_read_u32(&self, addr: u64) -> Result<u32>;
+    fn try_read_u64(&self, addr: u64) -> Result<u64>;
+    fn try_write_u8(&mut self, addr: u64, val: u8) -> Result<()>;
+    fn try_write_u16(&mut self, addr: u64, val: u16) -> Result<()>;
+    fn try_write_u32(&mut self, addr: u64, val: u32) -> Result<()>;
+    fn try_write_u64(&mut self, addr: u64, val: u64) -> Result<()>;
+
+    /// Copy `buf.len()` bytes starting at `addr` into `buf`.
+    fn read_bytes(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+        for (i, b) in buf.iter_mut().enumerate() {
+            *b = self.try_read_u8(byte_addr(addr, i)?)?;
+        }
+        Ok(())
+    }
+
+    /// Copy `data` to `addr`.
+    fn write_bytes(&mut self, addr: u64, data: &[u8]) -> Result<()> {
+        for (i, b) in data.iter().enumerate() {
+            self.try_write_u8(byte_addr(addr, i)?, *b)?;
+        }
+        Ok(())
+    }
+}
+
+/// Address of byte `i` of a bulk access at `addr`.
+fn byte_addr(addr: u64, i: usize) -> Result<u64> {
+    addr.checked_add(i as u64).ok_or(MmioError::OutOfRange { addr, base: 0, len: 0 })
+}
+
+/// Implement `MemoryBus` by forwarding to the type's inherent accessors.
+macro_rules! forward_bus {
+    ($ty:ident) => {
+        impl MemoryBus for $ty {
+            fn try_read_u8(&self, addr: u64) -> Result<u8> {
+                $ty::try_read_u8(self, addr)
+            }
+
+            fn try_read_u16(&self, addr: u64) -> Result<u16> {
+                $ty::try_read_u16(self, addr)
+            }
+
+            fn try_read_u32(&self, addr: u64) -> Result<u32> {
+                $ty::try_read_u32(self, addr)
+            }
+
+            fn try_read_u64(&self, addr: u64) -> Result<u64> {
+                $ty::try_read_u64(self, addr)
+            }
+
+            fn try_write_u8(&mut self, addr: u64, val: u8) -> Result<()> {
+                $ty::try_write_u8(self, addr, val)
+            }
+
+            fn try_write_u16(&mut self, addr: u64, val: u16) -> Result<()> {
+                $ty::try_write_u16(self, addr, val)
+            }
+
+            fn try_write_u32(&mut self, addr: u64, val: u32) -> Result<()> {
+                $ty::try_write_u32(self, addr, val)
+            }
+
+            fn try_write_u64(&mut self, addr: u64, val: u64) -> Result<()> {
+                $ty::try_write_u64(self, addr, val)
+            }
+
+            fn read_bytes(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+                $ty::read_into(self, addr, buf)
+            }
+
+            fn write_bytes(&mut self, addr: u64, data: &[u8]) -> Result<()> {
+                $ty::write_from(self, addr, data)
+            }
+        }
+    };
+}
+
+forward_bus!(Mmio);
+forward_bus!(MmioRegion);
+
+/// Reads go to the mapping; writes fail with `MmioError::ReadOnly`.
+impl MemoryBus for MmioRo {
+    fn try_read_u8(&self, addr: u64) -> Result<u8> {
+        MmioRo::try_read_u8(self, addr)
+    }
+
+    fn try_read_u16(&self, addr: u64) -> Result<u16> {
+        MmioRo::try_read_u16(self, addr)
+    }
+
+    fn try_read_u32(&self, addr: u64) -> Result<u32> {
+        MmioRo::try_read_u32(self, addr)
+    }
+
+    fn try_read_u64(&self, addr: u64) -> Result<u64> {
+        MmioRo::try_read_u64(self, addr)
+    }
+
+    fn try_write_u8(&mut self, addr: u64, _val: u8) -> Result<()> {
+        Err(MmioError::ReadOnly { addr })
+    }
+
+    fn try_write_u16(&mut self, addr: u64, _val: u16) -> Result<()> {
+        Err(MmioError::ReadOnly { addr })
+    }
+
+    fn try_write_u32(&mut self, addr: u64, _val: u32) -> Result<()> {
+        Err(MmioError::ReadOnly { addr })
+    }
+
+    fn try_write_u64(&mut self, addr: u64, _val: u64) -> Result<()> {
+        Err(MmioError::ReadOnly { addr })
+    }
+
+    fn read_bytes(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+        MmioRo::read_into(self, addr, buf)
+    }
+
+    fn write_bytes(&mut self, addr: u64, _data: &[u8]) -> Result<()> {
+        Err(MmioError::ReadOnly { addr })
+    }
+}
+
+/// Direction of a recorded `MockBus` access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessKind {
+    Read,
+    Write,
+}
+
+/// One access seen by a `MockBus`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Access {
+    pub kind: AccessKind,
+    pub addr: u64,
+    /// Width in bytes.
+    pub width: usize,
+    /// Value read or written, zero-extended.
+    pub value: u64,
+}
+
+/// `MemoryBus` over a zero-initialised buffer standing in for `len` bytes at
+/// physical `base`. Every successful access is recorded, in order.
+#[derive(Debug)]
+pub struct MockBus {
+    base: u64,
+    mem: Vec<u8>,
+    log: RefCell<Vec<Access>>,
+}
+
+impl MockBus {
+    pub fn new(base: u64, len: usize) -> Self {
+        MockBus { base, mem: vec![0; len], log: RefCell::new(Vec::new()) }
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Backing memory; reading or changing it directly is not recorded.
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        &mut self.mem
+    }
+
+    /// Accesses recorded so far.
+    pub fn accesses(&self) -> Vec<Access> {
+        self.log.borrow().clone()
+    }
+
+    /// Return and forget the recorded accesses.
+    pub fn take_accesses(&self) -> Vec<Access> {
+        self.log.take()
+    }
+
+    fn span(&self, addr: u64, len: usize) -> Result<usize> {
+        addr.checked_sub(self.base)
+            .filter(|rel| rel.checked_add(len as u64).is_some_and(|end| end <= self.mem.len() as u64))
+            .map(|rel| rel as usize)
+            .ok_or(MmioError::OutOfRange { addr, base: self.base, len: self.mem.len() })
+    }
+
+    fn offset(&self, addr: u64, width: usize) -> Result<usize> {
+        let rel = self.span(addr, width)?;
+        if !addr.is_multiple_of(width as u64) {
+            return Err(MmioError::Misaligned { addr, align: width });
+        }
+        Ok(rel)
+    }
+
+    fn read(&self, addr: u64, width: usize) -> Result<u64> {
+        let off = self.offset(addr, width)?;
+        let mut bytes = [0u8; 8];
+        bytes[..width].copy_from_slice(&self.mem[off..off + width]);
+        let value = u64::from_le_bytes(bytes);
+        self.log.borrow_mut().push(Access { kind: AccessKind::Read, addr, width, value });
+        Ok(value)
+    }
+
+    fn write(&mut self, addr: u64, width: usize, value: u64) -> Result<()> {
+        let off = self.offset(addr, width)?;
+        self.mem[off..off + width].copy_from_slice(&value.to_le_bytes()[..width]);
+        self.log.get_mut().push(Access { kind: AccessKind::Write, addr, width, value });
+        Ok(())
+    }
+}
+
+impl MemoryBus for MockBus {
+    fn try_read_u8(&self, addr: u64) -> Result<u8> {
+        self.read(addr, 1).map(|v| v as u8)
+    }
+
+    fn try_read_u16(&self, addr: u64) -> Result<u16> {
+        self.read(addr, 2).map(|v| v as u16)
+    }
+
+    fn try_read_u32(&self, addr: u64) -> Result<u32> {
+        self.read(addr, 4).map(|v| v as u32)
+    }
+
+    fn try_read_u64(&self, addr: u64) -> Result<u64> {
+        self.read(addr, 8)
+    }
+
+    fn try_write_u8(&mut self, addr: u64, val: u8) -> Result<()> {
+        self.write(addr, 1, val as u64)
+    }
+
+    fn try_write_u16(&mut self, addr: u64, val: u16) -> Result<()> {
+        self.write(addr, 2, val as u64)
+    }
+
+    fn try_write_u32(&mut self, addr: u64, val: u32) -> Result<()> {
+        self.write(addr, 4, val as u64)
+    }
+
+    fn try_write_u64(&mut self, addr: u64, val: u64) -> Result<()> {
+        self.write(addr, 8, val)
+    }
+
+    fn read_bytes(&self, addr: u64, buf: &mut [u8]) -> Result<()> {
+        let off = self.span(addr, buf.len())?;
+        buf.copy_from_slice(&self.mem[off..off + buf.len()]);
+        let mut log = self.log.borrow_mut();
+        for (i, &b) in buf.iter().enumerate() {
+            log.push(Access { kind: AccessKind::Read, addr: addr + i as u64, width: 1, value: b as u64 });
+        }
+        Ok(())
+    }
+
+    fn write_bytes(&mut self, addr: u64, data: &[u8]) -> Result<()> {
+        let off = self.span(addr, data.len())?;
+        self.mem[off..off + data.len()].copy_from_slice(data);
+        let log = self.log.get_mut();
+        for (i, &b) in data.iter().enumerate() {
+            log.push(Access { kind: AccessKind::Write, addr: addr + i as u64, width: 1, value: b as u64 });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_records_accesses() {
+        let mut bus = MockBus::new(0x4a30_0000, 0x100);
+        bus.try_write_u32(0x4a30_0010, 0xdead_beef).unwrap();
+        assert_eq!(bus.try_read_u16(0x4a30_0012).unwrap(), 0xdead);
+        assert_eq!(&bus.memory()[0x10..0x14], &[0xef, 0xbe, 0xad, 0xde]);
+        assert_eq!(
+            bus.take_accesses(),
+            vec![
+                Access { kind: AccessKind::Write, addr: 0x4a30_0010, width: 4, value: 0xdead_beef },
+                Access { kind: AccessKind::Read, addr: 0x4a30_0012, width: 2, value: 0xdead },
+            ]
+        );
+
+        let mut buf = [0u8; 3];
+        bus.read_bytes(0x4a30_0011, &mut buf).unwrap();
+        assert_eq!(buf, [0xbe, 0xad, 0xde]);
+        assert_eq!(bus.accesses().len(), 3);
+        assert!(matches!(bus.try_read_u32(0x4a30_0101), Err(MmioError::OutOfRange { .. })));
+        assert!(matches!(bus.try_write_u64(0x4a30_0004, 0), Err(MmioError::Misaligned { .. })));
+    }
+
+    #[test]
+    fn mock_bulk_access_checks_whole_range_first() {
+        let mut bus = MockBus::new(0x4a30_0000, 0x10);
+        let err = bus.write_bytes(0x4a30_000e, &[1, 2, 3]).unwrap_err();
+        assert!(matches!(err, MmioError::OutOfRange { .. }));
+        assert_eq!(bus.memory(), &[0u8; 0x10]);
+        assert!(bus.accesses().is_empty());
+
+        let mut buf = [0u8; 2];
+        assert!(bus.read_bytes(u64::MAX, &mut buf).is_err());
+        bus.write_bytes(0x4a30_000e, &[1, 2]).unwrap();
+        bus.read_bytes(0x4a30_000e, &mut buf).unwrap();
+        assert_eq!(buf, [1, 2]);
+        assert_eq!(bus.take_accesses().len(), 4);
+    }
+
+    /// Bus that only implements the fixed-width accessors.
+    struct ByteBus(MockBus);
+
+    impl MemoryBus for ByteBus {
+        fn try_read_u8(&self, addr: u64) -> Result<u8> {
+            self.0.try_read_u8(addr)
+        }
+        fn try_read_u16(&self, addr: u64) -> Result<u16> {
+            self.0.try_read_u16(addr)
+        }
+        fn try_read_u32(&self, addr: u64) -> Result<u32> {
+            self.0.try_read_u32(addr)
+        }
+        fn try_read_u64(&self, addr: u64) -> Result<u64> {
+            self.0.try_read_u64(addr)
+        }
+        fn try_write_u8(&mut self, addr: u64, val: u8) -> Result<()> {
+            self.0.try_write_u8(addr, val)
+        }
+        fn try_write_u16(&mut self, addr: u64, val: u16) -> Result<()> {
+            self.0.try_write_u16(addr, val)
+        }
+        fn try_write_u32(&mut self, addr: u64, val: u32) -> Result<()> {
+            self.0.try_write_u32(addr, val)
+        }
+        fn try_write_u64(&mut self, addr: u64, val: u64) -> Result<()> {
+            self.0.try_write_u64(addr, val)
+        }
+    }
+
+    #[test]
+    fn default_bulk_access_stops_at_end_of_address_space() {
+        let mut bus = ByteBus(MockBus::new(u64::MAX, 1));
+        let mut buf = [0u8; 2];
+        assert!(matches!(bus.read_bytes(u64::MAX, &mut buf), Err(MmioError::OutOfRange { .. })));
+        assert!(matches!(bus.write_bytes(u64::MAX, &[1, 2]), Err(MmioError::OutOfRange { .. })));
+    }
+}
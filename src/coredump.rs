@@ -0,0 +1,307 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::firmware::{self, PruFirmware};
+use crate::remoteproc::{RemoteProc, RemoteProcState, Result};
+
+const SYS_DEVCOREDUMP: &str = "/sys/class/devcoredump";
+
+/// A crash seen by `CrashMonitor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crash {
+    pub time: SystemTime,
+    /// State when the crash was noticed (`Crashed`, or already recovering).
+    pub state: RemoteProcState,
+    /// Where the devcoredump was saved, if one was produced.
+    pub dump: Option<PathBuf>,
+}
+
+/// Watches a remoteproc for crashes and saves the kernel's devcoredump.
+///
+/// A crash is noticed either by the `crashed` state or by a devcoredump
+/// appearing for the core; with recovery enabled the kernel restarts the
+/// core quickly, so the dump is often the only trace left. Each devcoredump
+/// is reported once: without a `dump_dir` it is left to the kernel (which
+/// drops it after about five minutes) but not reported again. Likewise a
+/// core that stays `crashed` (recovery disabled) is reported once, until it
+/// leaves that state or produces another dump.
+pub struct CrashMonitor<'a> {
+    rproc: &'a RemoteProc,
+    dump_dir: Option<PathBuf>,
+    interval: Duration,
+    dump_wait: Duration,
+    /// Names of the devcoredumps already reported.
+    seen: RefCell<HashSet<String>>,
+    /// The core's current `crashed` episode was reported.
+    reported_crashed: Cell<bool>,
+}
+
+impl<'a> CrashMonitor<'a> {
+    pub fn new(rproc: &'a RemoteProc) -> Self {
+        CrashMonitor {
+            rproc,
+            dump_dir: None,
+            interval: Duration::from_millis(100),
+            dump_wait: Duration::from_secs(1),
+            seen: RefCell::new(HashSet::new()),
+            reported_crashed: Cell::new(false),
+        }
+    }
+
+    /// Save devcoredumps into `dir` as `<instance>-<unix secs>-<devcdN>.core`.
+    pub fn dump_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.dump_dir = Some(dir.as_ref().to_path_buf());
+        self
+    }
+
+    /// How often to check state (default 100 ms).
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long to wait for a dump after seeing `crashed` (default 1 s).
+    pub fn dump_wait(mut self, wait: Duration) -> Self {
+        self.dump_wait = wait;
+        self
+    }
+
+    /// Check once for a crash. Returns at once when there is none; after
+    /// seeing `crashed` it waits up to `dump_wait` for the devcoredump.
+    pub fn check(&self) -> Result<Option<Crash>> {
+        let state = self.rproc.state()?;
+        let devcd = self.new_devcoredump()?;
+        let crashed = state == RemoteProcState::Crashed;
+        if !crashed {
+            self.reported_crashed.set(false);
+        }
+        if devcd.is_none() && (!crashed || self.reported_crashed.get()) {
+            return Ok(None);
+        }
+        self.reported_crashed.set(crashed);
+
+        let deadline = Instant::now().checked_add(self.dump_wait);
+        let mut devcd = devcd;
+        while devcd.is_none() && deadline.is_none_or(|d| Instant::now() < d) {
+            let now = Instant::now();
+            thread::sleep(deadline.map_or(self.interval, |d| self.interval.min(d.saturating_duration_since(now))));
+            devcd = self.new_devcoredump()?;
+        }
+
+        let time = SystemTime::now();
+        let dump = match (devcd, &self.dump_dir) {
+            (Some((cd, name)), Some(dir)) => {
+                let secs = time.duration_since(SystemTime::UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+                let dest = dir.join(format!("{}-{}-{}.core", self.rproc.instance(), secs, name));
+                save_devcoredump(&cd, &dest)?;
+                self.seen.borrow_mut().insert(name);
+                Some(dest)
+            }
+            (Some((_, name)), None) => {
+                self.seen.borrow_mut().insert(name);
+                None
+            }
+            _ => None,
+        };
+        Ok(Some(Crash { time, state, dump }))
+    }
+
+    /// A pending devcoredump for the core that was not reported yet, with its name.
+    fn new_devcoredump(&self) -> Result<Option<(PathBuf, String)>> {
+        let seen = self.seen.borrow();
+        Ok(devcoredumps(self.rproc)?.into_iter().find_map(|dir| {
+            let name = dir.file_name()?.to_string_lossy().into_owned();
+            (!seen.contains(&name)).then_some((dir, name))
+        }))
+    }
+
+    /// Block until the core crashes or `timeout` elapses (`None` waits forever).
+    pub fn wait(&self, timeout: Option<Duration>) -> Result<Option<Crash>> {
+        // a timeout too large to represent is the same as none
+        let deadline = timeout.and_then(|t| Instant::now().checked_add(t));
+        loop {
+            if let Some(crash) = self.check()? {
+                return Ok(Some(crash));
+            }
+            if deadline.is_some_and(|d| Instant::now() >= d) {
+                return Ok(None);
+            }
+            thread::sleep(self.interval);
+        }
+    }
+}
+
+/// Find a pending devcoredump (`devcdN` directory) produced by `rproc`.
+pub fn find_devcoredump(rproc: &RemoteProc) -> Result<Option<PathBuf>> {
+    Ok(devcoredumps(rproc)?.into_iter().next())
+}
+
+/// All pending devcoredumps produced by `rproc`, oldest first.
+fn devcoredumps(rproc: &RemoteProc) -> Result<Vec<PathBuf>> {
+    let base = rproc.sysroot().resolve(SYS_DEVCOREDUMP);
+    let me = fs::canonicalize(rproc.path())?;
+    let entries = match fs::read_dir(&base) {
+        Ok(e) => e,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let mut out = Vec::new();
+    for entry in entries {
+        let dir = entry?.path();
+        if fs::canonicalize(dir.join("failing_device")).ok().as_ref() == Some(&me) {
+            out.push(dir);
+        }
+    }
+    // devcdN numbers only grow
+    out.sort_by_key(|d| {
+        let name = d.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+        name.trim_start_matches("devcd").parse::<u64>().unwrap_or(u64::MAX)
+    });
+    Ok(out)
+}
+
+/// Copy a devcoredump's `data` to `dest`, then release it in the kernel
+/// (any write to `data` frees the dump).
+pub fn save_devcoredump(devcd: &Path, dest: &Path) -> Result<()> {
+    let data = devcd.join("data");
+    fs::copy(&data, dest)?;
+    fs::write(&data, "1")?;
+    Ok(())
+}
+
+/// A remoteproc ELF core dump: one loadable segment per dumped memory region.
+#[derive(Debug, Clone)]
+pub struct CoreDump {
+    image: PruFirmware,
+}
+
+impl CoreDump {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> firmware::Result<Self> {
+        Ok(CoreDump { image: PruFirmware::from_file(path)? })
+    }
+
+    pub fn parse(data: Vec<u8>) -> firmware::Result<Self> {
+        Ok(CoreDump { image: PruFirmware::parse(data)? })
+    }
+
+    /// Dumped regions as `(device address, contents)`.
+    pub fn regions(&self) -> Vec<(u32, &[u8])> {
+        self.image
+            .segments()
+            .iter()
+            .map(|s| (s.addr, self.image.segment_data(s)))
+            .collect()
+    }
+
+    /// Contents of the region starting at device address `addr`.
+    pub fn region(&self, addr: u32) -> Option<&[u8]> {
+        self.regions().into_iter().find(|(a, _)| *a == addr).map(|(_, d)| d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firmware::tests::ElfBuilder;
+    use crate::sysroot::SysRoot;
+
+    #[test]
+    fn monitor_saves_dump_for_own_device() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = dir.path().join("sys/devices/platform/4a334000.pru/remoteproc/remoteproc1");
+        fs::create_dir_all(&dev).unwrap();
+        fs::write(dev.join("state"), "crashed\n").unwrap();
+        let class = dir.path().join("sys/class/remoteproc");
+        fs::create_dir_all(&class).unwrap();
+        std::os::unix::fs::symlink(&dev, class.join("remoteproc1")).unwrap();
+
+        let cd = dir.path().join("sys/class/devcoredump/devcd3");
+        fs::create_dir_all(&cd).unwrap();
+        fs::write(cd.join("data"), b"\x7fELF core").unwrap();
+        std::os::unix::fs::symlink(&dev, cd.join("failing_device")).unwrap();
+
+        let out = dir.path().join("dumps");
+        fs::create_dir_all(&out).unwrap();
+        let rp = RemoteProc::open_in(&SysRoot::new(dir.path()), "remoteproc1").unwrap();
+        let crash = CrashMonitor::new(&rp).dump_dir(&out).check().unwrap().unwrap();
+
+        assert_eq!(crash.state, RemoteProcState::Crashed);
+        let dump = crash.dump.unwrap();
+        assert!(dump.file_name().unwrap().to_str().unwrap().ends_with("-devcd3.core"));
+        assert_eq!(fs::read(dump).unwrap(), b"\x7fELF core");
+        assert_eq!(fs::read(cd.join("data")).unwrap(), b"1");
+    }
+
+    #[test]
+    fn monitor_reports_each_dump_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = dir.path().join("sys/devices/platform/4a334000.pru/remoteproc/remoteproc1");
+        fs::create_dir_all(&dev).unwrap();
+        // recovered already: only the dump is left
+        fs::write(dev.join("state"), "running\n").unwrap();
+        let class = dir.path().join("sys/class/remoteproc");
+        fs::create_dir_all(&class).unwrap();
+        std::os::unix::fs::symlink(&dev, class.join("remoteproc1")).unwrap();
+        let add_dump = |name: &str| {
+            let cd = dir.path().join("sys/class/devcoredump").join(name);
+            fs::create_dir_all(&cd).unwrap();
+            std::os::unix::fs::symlink(&dev, cd.join("failing_device")).unwrap();
+        };
+        add_dump("devcd1");
+
+        let rp = RemoteProc::open_in(&SysRoot::new(dir.path()), "remoteproc1").unwrap();
+        let monitor = CrashMonitor::new(&rp).dump_wait(Duration::ZERO);
+        let crash = monitor.check().unwrap().unwrap();
+        assert_eq!((crash.state, crash.dump), (RemoteProcState::Online, None));
+        assert_eq!(monitor.check().unwrap(), None);
+        add_dump("devcd2");
+        assert!(monitor.check().unwrap().is_some());
+        assert_eq!(monitor.wait(Some(Duration::from_millis(5))).unwrap(), None);
+    }
+
+    #[test]
+    fn monitor_reports_lasting_crash_once() {
+        let dir = tempfile::tempdir().unwrap();
+        let dev = dir.path().join("sys/devices/platform/4a334000.pru/remoteproc/remoteproc1");
+        fs::create_dir_all(&dev).unwrap();
+        fs::write(dev.join("state"), "crashed\n").unwrap();
+        let class = dir.path().join("sys/class/remoteproc");
+        fs::create_dir_all(&class).unwrap();
+        std::os::unix::fs::symlink(&dev, class.join("remoteproc1")).unwrap();
+
+        let rp = RemoteProc::open_in(&SysRoot::new(dir.path()), "remoteproc1").unwrap();
+        let monitor = CrashMonitor::new(&rp).dump_wait(Duration::ZERO);
+        let crash = monitor.check().unwrap().unwrap();
+        assert_eq!((crash.state, crash.dump), (RemoteProcState::Crashed, None));
+        assert_eq!(monitor.check().unwrap(), None);
+        assert_eq!(monitor.wait(Some(Duration::from_millis(5))).unwrap(), None);
+
+        // a dump produced later is still reported
+        let cd = dir.path().join("sys/class/devcoredump/devcd1");
+        fs::create_dir_all(&cd).unwrap();
+        std::os::unix::fs::symlink(&dev, cd.join("failing_device")).unwrap();
+        assert!(monitor.check().unwrap().is_some());
+        assert_eq!(monitor.check().unwrap(), None);
+
+        // crashing again after a recovery is a new episode
+        fs::write(dev.join("state"), "running\n").unwrap();
+        assert_eq!(monitor.check().unwrap(), None);
+        fs::write(dev.join("state"), "crashed\n").unwrap();
+        assert!(monitor.check().unwrap().is_some());
+    }
+
+    #[test]
+    fn core_regions() {
+        let mut b = ElfBuilder::pru();
+        b.segments.push((7, 0, vec![1, 2, 3, 4], 4));
+        b.segments.push((7, 0x2000, vec![5, 6], 2));
+        let core = CoreDump::parse(b.build()).unwrap();
+        assert_eq!(core.regions().len(), 2);
+        assert_eq!(core.region(0x2000), Some(&[5u8, 6][..]));
+    }
+}
@@ -0,0 +1,189 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::remoteproc::{RemoteProc, RemoteProcInfo, Result};
+use crate::sysroot::SysRoot;
+
+/// Kind of programmable core inside a PRU-ICSS / ICSSG.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum CoreKind {
+    Pru,
+    /// ICSSG auxiliary PRU (AM65x/AM64x).
+    Rtu,
+    /// ICSSG transmit PRU (AM65x/AM64x).
+    TxPru,
+}
+
+impl CoreKind {
+    /// Parse the suffix of a remoteproc `name` (`pru`, `pru0`, `rtu`, `txpru`, ...).
+    fn from_suffix(s: &str) -> Option<Self> {
+        match s.trim_end_matches(|c: char| c.is_ascii_digit()) {
+            "pru" => Some(CoreKind::Pru),
+            "rtu" => Some(CoreKind::Rtu),
+            "txpru" => Some(CoreKind::TxPru),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoreKind::Pru => "pru",
+            CoreKind::Rtu => "rtu",
+            CoreKind::TxPru => "txpru",
+        }
+    }
+}
+
+/// Address of a core: ICSS instance, core kind and index among cores of
+/// that kind in the instance. Instances and cores are numbered in order of
+/// their physical addresses, matching the SoC documentation
+/// (e.g. AM57xx PRU-ICSS2 PRU1 is `CoreId::pru(1, 1)`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CoreId {
+    pub icss: usize,
+    pub kind: CoreKind,
+    pub index: usize,
+}
+
+impl CoreId {
+    pub fn new(icss: usize, kind: CoreKind, index: usize) -> Self {
+        CoreId { icss, kind, index }
+    }
+
+    /// PRU core `index` of ICSS `icss`.
+    pub fn pru(icss: usize, index: usize) -> Self {
+        CoreId::new(icss, CoreKind::Pru, index)
+    }
+}
+
+impl fmt::Display for CoreId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "icss{}/{}{}", self.icss, self.kind.as_str(), self.index)
+    }
+}
+
+/// Cores of one ICSS by kind, as `(unit address, info)`.
+type IcssCores = BTreeMap<CoreKind, Vec<(u64, RemoteProcInfo)>>;
+
+/// A core found by `discover`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredCore {
+    pub id: CoreId,
+    pub info: RemoteProcInfo,
+}
+
+/// Enumerate the PRU-family remoteprocs under `root` and assign `CoreId`s.
+///
+/// A core's ICSS is the parent of its resolved `device` (the pruss node);
+/// cores without a `device` link are all placed in ICSS 0. Remoteprocs whose
+/// `name` is not a PRU, RTU or TX_PRU (e.g. the wakeup M3) are skipped, as
+/// are those whose attributes cannot be read or parsed, so one odd
+/// remoteproc does not hide the PRUs.
+pub fn discover(root: &SysRoot) -> Result<Vec<DiscoveredCore>> {
+    let mut groups: BTreeMap<(u64, PathBuf), IcssCores> = BTreeMap::new();
+    let mut names = RemoteProc::list_in(root)?;
+    names.sort();
+    for name in names {
+        let Ok(info) = RemoteProc::open_in(root, &name).and_then(|rp| rp.info()) else {
+            continue;
+        };
+        let Some((addr, suffix)) = info.name.split_once('.') else {
+            continue;
+        };
+        let Some(kind) = CoreKind::from_suffix(suffix) else {
+            continue;
+        };
+        let parent = info
+            .device
+            .as_deref()
+            .and_then(Path::parent)
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let parent_addr = parent.file_name().and_then(|n| n.to_str()).map_or(0, unit_address);
+        groups
+            .entry((parent_addr, parent))
+            .or_default()
+            .entry(kind)
+            .or_default()
+            .push((unit_address(addr), info));
+    }
+
+    let mut out = Vec::new();
+    for (icss, kinds) in groups.into_values().enumerate() {
+        for (kind, mut cores) in kinds {
+            cores.sort_by_key(|(addr, _)| *addr);
+            for (index, (_, info)) in cores.into_iter().enumerate() {
+                out.push(DiscoveredCore { id: CoreId::new(icss, kind, index), info });
+            }
+        }
+    }
+    out.sort_by_key(|c| c.id);
+    Ok(out)
+}
+
+/// Parse the hex unit address at the start of a device name (`4a300000.pruss`).
+fn unit_address(name: &str) -> u64 {
+    let hex = name.split('.').next().unwrap_or("");
+    u64::from_str_radix(hex, 16).unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::fs;
+
+    /// Add a remoteproc `instance` named `name` whose device lives under `pruss`.
+    pub(crate) fn add_core(dir: &Path, instance: &str, pruss: &str, name: &str) {
+        let dev = dir.join("sys/devices/platform/ocp").join(pruss).join(name);
+        let rp = dir.join("sys/class/remoteproc").join(instance);
+        fs::create_dir_all(&dev).unwrap();
+        fs::create_dir_all(&rp).unwrap();
+        fs::write(rp.join("name"), format!("{}\n", name)).unwrap();
+        fs::write(rp.join("state"), "offline\n").unwrap();
+        fs::write(rp.join("firmware"), "fw\n").unwrap();
+        std::os::unix::fs::symlink(&dev, rp.join("device")).unwrap();
+    }
+
+    #[test]
+    fn discovers_am57xx_layout() {
+        let dir = tempfile::tempdir().unwrap();
+        add_core(dir.path(), "remoteproc4", "4b280000.pruss", "4b2b8000.pru");
+        add_core(dir.path(), "remoteproc3", "4b280000.pruss", "4b2b4000.pru");
+        add_core(dir.path(), "remoteproc2", "4b200000.pruss", "4b238000.pru");
+        add_core(dir.path(), "remoteproc1", "4b200000.pruss", "4b234000.pru");
+        add_core(dir.path(), "remoteproc0", "ocp", "58820000.ipu");
+        // a DSP with a recovery value we do not know, and a node without `name`
+        add_core(dir.path(), "remoteproc5", "ocp", "40800000.dsp");
+        fs::write(dir.path().join("sys/class/remoteproc/remoteproc5/recovery"), "paused\n").unwrap();
+        add_core(dir.path(), "remoteproc6", "ocp", "44d00000.wkup_m3");
+        fs::remove_file(dir.path().join("sys/class/remoteproc/remoteproc6/name")).unwrap();
+
+        let cores = discover(&SysRoot::new(dir.path())).unwrap();
+        let ids: Vec<_> = cores.iter().map(|c| (c.id.to_string(), c.info.instance.as_str())).collect();
+        assert_eq!(
+            ids,
+            vec![
+                ("icss0/pru0".to_string(), "remoteproc1"),
+                ("icss0/pru1".to_string(), "remoteproc2"),
+                ("icss1/pru0".to_string(), "remoteproc3"),
+                ("icss1/pru1".to_string(), "remoteproc4"),
+            ]
+        );
+    }
+
+    #[test]
+    fn discovers_icssg_kinds() {
+        let dir = tempfile::tempdir().unwrap();
+        add_core(dir.path(), "remoteproc1", "30000000.icssg", "30034000.pru");
+        add_core(dir.path(), "remoteproc2", "30000000.icssg", "30004000.rtu");
+        add_core(dir.path(), "remoteproc3", "30000000.icssg", "3000a000.txpru");
+        add_core(dir.path(), "remoteproc4", "30000000.icssg", "30038000.pru");
+
+        let cores = discover(&SysRoot::new(dir.path())).unwrap();
+        let find = |id: CoreId| cores.iter().find(|c| c.id == id).unwrap().info.instance.clone();
+        assert_eq!(find(CoreId::pru(0, 1)), "remoteproc4");
+        assert_eq!(find(CoreId::new(0, CoreKind::Rtu, 0)), "remoteproc2");
+        assert_eq!(find(CoreId::new(0, CoreKind::TxPru, 0)), "remoteproc3");
+    }
+}
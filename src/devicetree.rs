@@ -0,0 +1,488 @@
+//! Discovery of PRUSS / ICSSG instances and their regions from the flattened
+//! device tree exported by the kernel.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::board::{Region, RegionDef};
+use crate::cores::CoreKind;
+use crate::mmio::{Mmio, MmioError};
+use crate::sysroot::SysRoot;
+
+/// Where the kernel exports the live device tree, in order of preference.
+pub(crate) const DT_BASES: [&str; 2] = ["/proc/device-tree", "/sys/firmware/devicetree/base"];
+
+#[derive(Debug, Error)]
+pub enum DeviceTreeError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("mmio: {0}")]
+    Mmio(#[from] MmioError),
+    #[error("malformed property {path}: {reason}")]
+    Malformed { path: PathBuf, reason: String },
+    #[error("{node}: no region {region}")]
+    NoRegion { node: PathBuf, region: Region },
+    #[error("{0}: address not translatable to a physical address")]
+    Untranslatable(PathBuf),
+}
+
+/// A PRUSS / ICSSG node with its regions translated to physical addresses.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DtPruss {
+    /// Device-tree node directory.
+    pub node: PathBuf,
+    /// First matching `compatible` string (e.g. `ti,am3356-pruss`).
+    pub compatible: String,
+    pub base: u64,
+    pub size: usize,
+    /// Regions relative to `base`.
+    pub regions: Vec<RegionDef>,
+}
+
+impl DtPruss {
+    /// Physical address and size of `region`.
+    pub fn region(&self, region: Region) -> Option<(u64, usize)> {
+        self.regions
+            .iter()
+            .find(|r| r.region == region)
+            .map(|r| (self.base + r.offset, r.size))
+    }
+
+    /// Map `region` of this instance.
+    pub fn map(&self, region: Region) -> Result<Mmio> {
+        let (base, len) = self
+            .region(region)
+            .ok_or_else(|| DeviceTreeError::NoRegion { node: self.node.clone(), region })?;
+        Ok(Mmio::map(base, len)?)
+    }
+
+    /// Map the whole instance.
+    pub fn map_pruss(&self) -> Result<Mmio> {
+        Ok(Mmio::map(self.base, self.size)?)
+    }
+}
+
+/// Discover the enabled PRUSS instances of the running system.
+pub fn discover() -> Result<Vec<DtPruss>> {
+    discover_in(&SysRoot::host())
+}
+
+/// Like `discover`, resolving the device-tree location under `root`.
+pub fn discover_in(root: &SysRoot) -> Result<Vec<DtPruss>> {
+    let mut last = io::Error::from(io::ErrorKind::NotFound);
+    for base in DT_BASES {
+        let dir = root.resolve(base);
+        match fs::metadata(&dir) {
+            Ok(_) => return discover_at(&dir),
+            Err(e) => last = e,
+        }
+    }
+    Err(last.into())
+}
+
+/// Discover PRUSS instances in the device tree rooted at `dir`, sorted by
+/// base address (the numbering `CoreId::icss` uses).
+pub fn discover_at(dir: &Path) -> Result<Vec<DtPruss>> {
+    let mut out = Vec::new();
+    let defaults = Bus { address_cells: 2, size_cells: 1, ranges: Vec::new() };
+    walk(dir, &[child_bus(dir, &defaults)?], &mut out)?;
+    out.sort_by_key(|p| p.base);
+    Ok(out)
+}
+
+/// Address decoding context for the children of a node.
+#[derive(Debug, Clone)]
+struct Bus {
+    address_cells: usize,
+    size_cells: usize,
+    /// `(child, parent, size)` windows into the parent bus; empty is identity.
+    ranges: Vec<(u64, u64, u64)>,
+}
+
+/// A `reg` entry translated to a physical address, with its `reg-names` name.
+struct Reg {
+    name: Option<String>,
+    addr: u64,
+    size: usize,
+}
+
+fn walk(dir: &Path, buses: &[Bus], out: &mut Vec<DtPruss>) -> Result<()> {
+    for node in child_nodes(dir)? {
+        if !enabled(&node) {
+            continue;
+        }
+        let bus = child_bus(&node, buses.last().unwrap())?;
+        let mut chain = buses.to_vec();
+        chain.push(bus);
+        match pruss_compatible(&node) {
+            Some(compatible) => out.push(decode_pruss(&node, compatible, &chain)?),
+            None => walk(&node, &chain, out)?,
+        }
+    }
+    Ok(())
+}
+
+fn decode_pruss(node: &Path, compatible: String, chain: &[Bus]) -> Result<DtPruss> {
+    let own = regs(node, &chain[..chain.len() - 1])?;
+    let mut found: Vec<(Region, u64, usize)> = Vec::new();
+    let mut cores: Vec<(CoreKind, u64, Vec<Reg>)> = Vec::new();
+    let mut ieps = Vec::new();
+
+    name_regs(node_base(node), own.iter(), &mut found, &mut ieps);
+    collect(node, chain, &mut found, &mut cores, &mut ieps)?;
+
+    cores.sort_by_key(|(kind, addr, _)| (*kind, *addr));
+    let mut counts = [0usize; 3];
+    for (kind, _, regs) in &cores {
+        let index = counts[*kind as usize];
+        counts[*kind as usize] += 1;
+        for r in regs {
+            let region = match r.name.as_deref() {
+                Some("iram") => Region::Iram(*kind, index),
+                Some("control") => Region::Ctrl(*kind, index),
+                _ => continue,
+            };
+            found.push((region, r.addr, r.size));
+        }
+    }
+    ieps.sort();
+    for (n, (addr, size)) in ieps.into_iter().enumerate() {
+        found.push((Region::Iep(n), addr, size));
+    }
+
+    let (base, size) = match own.first() {
+        // older kernels list the memories directly in the pruss node's `reg`
+        Some(r) if own.len() == 1 || r.name.is_none() => (r.addr, r.size),
+        _ => {
+            let lo = found.iter().map(|f| f.1).min().unwrap_or(0);
+            let mut hi = 0;
+            for f in &found {
+                let end = f.1.checked_add(f.2 as u64).ok_or_else(|| malformed(node, "reg", "region ends past 2^64"))?;
+                hi = hi.max(end);
+            }
+            (lo, (hi - lo) as usize)
+        }
+    };
+    let mut regions: Vec<RegionDef> = found
+        .into_iter()
+        .filter(|(_, addr, _)| *addr >= base)
+        .map(|(region, addr, size)| RegionDef { region, offset: addr - base, size })
+        .collect();
+    regions.sort_by_key(|r| r.offset);
+    Ok(DtPruss { node: node.to_path_buf(), compatible, base, size, regions })
+}
+
+/// Gather regions from the descendants of a pruss node.
+fn collect(
+    dir: &Path,
+    chain: &[Bus],
+    found: &mut Vec<(Region, u64, usize)>,
+    cores: &mut Vec<(CoreKind, u64, Vec<Reg>)>,
+    ieps: &mut Vec<(u64, usize)>,
+) -> Result<()> {
+    for node in child_nodes(dir)? {
+        let regs = regs(&node, chain)?;
+        let name = node_base(&node);
+        let kind = match name {
+            "pru" => Some(CoreKind::Pru),
+            "rtu" => Some(CoreKind::Rtu),
+            "txpru" => Some(CoreKind::TxPru),
+            _ => None,
+        };
+        match kind {
+            Some(kind) => {
+                let addr = regs.first().map_or(u64::MAX, |r| r.addr);
+                cores.push((kind, addr, regs));
+            }
+            None => {
+                name_regs(name, regs.iter(), found, ieps);
+                let bus = child_bus(&node, chain.last().unwrap())?;
+                let mut sub = chain.to_vec();
+                sub.push(bus);
+                collect(&node, &sub, found, cores, ieps)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Name `regs` of a non-core node from `reg-names`, or from the node name
+/// for single-region peripherals.
+fn name_regs<'a>(
+    node_name: &str,
+    regs: impl Iterator<Item = &'a Reg>,
+    found: &mut Vec<(Region, u64, usize)>,
+    ieps: &mut Vec<(u64, usize)>,
+) {
+    for r in regs {
+        let name = r.name.as_deref().unwrap_or(node_name);
+        let region = match name {
+            "iep" => {
+                ieps.push((r.addr, r.size));
+                continue;
+            }
+            "interrupt-controller" => Some(Region::Intc),
+            "serial" => Some(Region::Uart),
+            _ => Region::from_name(name),
+        };
+        if let Some(region) = region {
+            found.push((region, r.addr, r.size));
+        }
+    }
+}
+
+/// Decode `reg`/`reg-names` of `node`, whose parent bus is `chain.last()`.
+fn regs(node: &Path, chain: &[Bus]) -> Result<Vec<Reg>> {
+    let Some(raw) = read_prop(node, "reg")? else {
+        return Ok(Vec::new());
+    };
+    let bus = chain.last().unwrap();
+    let names = read_prop(node, "reg-names")?.map(|b| strings(&b)).unwrap_or_default();
+    let cells = cells(&raw).ok_or_else(|| malformed(node, "reg", "length not a multiple of 4"))?;
+    let stride = bus.address_cells + bus.size_cells;
+    if stride == 0 || !cells.len().is_multiple_of(stride) {
+        return Err(malformed(node, "reg", "does not match #address-cells/#size-cells"));
+    }
+    cells
+        .chunks(stride)
+        .enumerate()
+        .map(|(i, c)| {
+            let addr = translate(join(&c[..bus.address_cells]), chain)
+                .ok_or_else(|| DeviceTreeError::Untranslatable(node.to_path_buf()))?;
+            Ok(Reg { name: names.get(i).cloned(), addr, size: join(&c[bus.address_cells..]) as usize })
+        })
+        .collect()
+}
+
+/// Map a bus address through every enclosing `ranges`, innermost first.
+fn translate(mut addr: u64, chain: &[Bus]) -> Option<u64> {
+    for bus in chain.iter().rev() {
+        if bus.ranges.is_empty() {
+            continue;
+        }
+        let &(child, parent, _) = bus.ranges.iter().find(|(c, _, s)| addr >= *c && addr - c < *s)?;
+        addr = (addr - child).checked_add(parent)?;
+    }
+    Some(addr)
+}
+
+/// Decoding context `node` provides for its children.
+fn child_bus(node: &Path, parent: &Bus) -> Result<Bus> {
+    let address_cells = read_u32_prop(node, "#address-cells")?.map_or(parent.address_cells, |v| v as usize);
+    let size_cells = read_u32_prop(node, "#size-cells")?.map_or(parent.size_cells, |v| v as usize);
+    let mut ranges = Vec::new();
+    if let Some(raw) = read_prop(node, "ranges")? {
+        let cells = cells(&raw).ok_or_else(|| malformed(node, "ranges", "length not a multiple of 4"))?;
+        let stride = address_cells + parent.address_cells + size_cells;
+        if stride == 0 || !cells.len().is_multiple_of(stride) {
+            return Err(malformed(node, "ranges", "does not match cell counts"));
+        }
+        for c in cells.chunks(stride) {
+            let (child, rest) = c.split_at(address_cells);
+            let (parent_addr, size) = rest.split_at(parent.address_cells);
+            ranges.push((join(child), join(parent_addr), join(size)));
+        }
+    }
+    Ok(Bus { address_cells, size_cells, ranges })
+}
+
+/// The PRUSS-level `compatible` of `node`, if any (`ti,am3356-pruss`,
+/// `ti,am4376-pruss1`, `ti,am642-icssg`, ...).
+fn pruss_compatible(node: &Path) -> Option<String> {
+    let raw = fs::read(node.join("compatible")).ok()?;
+    strings(&raw).into_iter().find(|c| {
+        let tail = c.trim_end_matches(|ch: char| ch.is_ascii_digit());
+        c.starts_with("ti,") && (tail.ends_with("-pruss") || tail.ends_with("-icssg"))
+    })
+}
+
+fn enabled(node: &Path) -> bool {
+    match fs::read(node.join("status")) {
+        Ok(raw) => matches!(strings(&raw).first().map(String::as_str), Some("okay" | "ok")),
+        Err(_) => true,
+    }
+}
+
+fn child_nodes(dir: &Path) -> Result<Vec<PathBuf>> {
+    let mut nodes = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            nodes.push(entry.path());
+        }
+    }
+    nodes.sort();
+    Ok(nodes)
+}
+
+/// Node name without the unit address (`pru@34000` -> `pru`).
+fn node_base(node: &Path) -> &str {
+    let name = node.file_name().and_then(|n| n.to_str()).unwrap_or("");
+    name.split('@').next().unwrap_or(name)
+}
+
+fn read_prop(node: &Path, name: &str) -> Result<Option<Vec<u8>>> {
+    match fs::read(node.join(name)) {
+        Ok(b) => Ok(Some(b)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn read_u32_prop(node: &Path, name: &str) -> Result<Option<u32>> {
+    match read_prop(node, name)? {
+        Some(raw) => match cells(&raw).as_deref() {
+            Some([v]) => Ok(Some(*v)),
+            _ => Err(malformed(node, name, "expected one cell")),
+        },
+        None => Ok(None),
+    }
+}
+
+/// Split a property into big-endian 32-bit cells.
+fn cells(raw: &[u8]) -> Option<Vec<u32>> {
+    if !raw.len().is_multiple_of(4) {
+        return None;
+    }
+    Some(raw.chunks(4).map(|c| u32::from_be_bytes(c.try_into().unwrap())).collect())
+}
+
+fn join(cells: &[u32]) -> u64 {
+    cells.iter().fold(0u64, |acc, c| (acc << 32) | *c as u64)
+}
+
+fn strings(raw: &[u8]) -> Vec<String> {
+    raw.split(|b| *b == 0)
+        .filter(|s| !s.is_empty())
+        .map(|s| String::from_utf8_lossy(s).into_owned())
+        .collect()
+}
+
+fn malformed(node: &Path, prop: &str, reason: &str) -> DeviceTreeError {
+    DeviceTreeError::Malformed { path: node.join(prop), reason: reason.to_string() }
+}
+
+pub type Result<T> = std::result::Result<T, DeviceTreeError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::AM335X;
+
+    /// Builds a device-tree directory the way the kernel exports it.
+    struct Fixture {
+        dir: tempfile::TempDir,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            let f = Fixture { dir: tempfile::tempdir().unwrap() };
+            fs::create_dir_all(f.base()).unwrap();
+            f.prop("", "#address-cells", &be(&[1]));
+            f.prop("", "#size-cells", &be(&[1]));
+            f.prop("", "compatible", b"ti,am335x-bone-black\0ti,am33xx\0");
+            f
+        }
+
+        fn base(&self) -> PathBuf {
+            self.dir.path().join("proc/device-tree")
+        }
+
+        fn prop(&self, node: &str, name: &str, value: &[u8]) {
+            let dir = self.base().join(node);
+            fs::create_dir_all(&dir).unwrap();
+            fs::write(dir.join(name), value).unwrap();
+        }
+    }
+
+    fn be(cells: &[u32]) -> Vec<u8> {
+        cells.iter().flat_map(|c| c.to_be_bytes()).collect()
+    }
+
+    /// AM335x layout of current mainline kernels: pruss inside a target module.
+    fn am335x() -> Fixture {
+        let f = Fixture::new();
+        let tm = "ocp/interconnect@4a000000/segment@0/target-module@300000";
+        f.prop("ocp", "ranges", b"");
+        f.prop("ocp/interconnect@4a000000", "ranges", &be(&[0, 0x4a00_0000, 0x0100_0000]));
+        f.prop("ocp/interconnect@4a000000", "#address-cells", &be(&[1]));
+        f.prop("ocp/interconnect@4a000000/segment@0", "ranges", &be(&[0, 0, 0x0080_0000]));
+        f.prop(tm, "ranges", &be(&[0, 0x30_0000, 0x8_0000]));
+        let p = format!("{}/pruss@0", tm);
+        f.prop(&p, "compatible", b"ti,am3356-pruss\0");
+        f.prop(&p, "reg", &be(&[0, 0x8_0000]));
+        f.prop(&p, "ranges", b"");
+        f.prop(&format!("{}/memories@0", p), "reg", &be(&[0, 0x2000, 0x2000, 0x2000, 0x1_0000, 0x3000]));
+        f.prop(&format!("{}/memories@0", p), "reg-names", b"dram0\0dram1\0shrdram2\0");
+        f.prop(&format!("{}/cfg@26000", p), "reg", &be(&[0x2_6000, 0x2000]));
+        f.prop(&format!("{}/interrupt-controller@20000", p), "reg", &be(&[0x2_0000, 0x2000]));
+        f.prop(&format!("{}/iep@2e000", p), "reg", &be(&[0x2_e000, 0x31c]));
+        for (node, iram, ctrl) in [("pru@38000", 0x3_8000, 0x2_4000), ("pru@34000", 0x3_4000, 0x2_2000)] {
+            let n = format!("{}/{}", p, node);
+            f.prop(&n, "reg", &be(&[iram, 0x2000, ctrl, 0x400, ctrl + 0x400, 0x100]));
+            f.prop(&n, "reg-names", b"iram\0control\0debug\0");
+        }
+        f
+    }
+
+    #[test]
+    fn discovers_am335x_pruss() {
+        let f = am335x();
+        let found = discover_in(&SysRoot::new(f.dir.path())).unwrap();
+        assert_eq!(found.len(), 1);
+        let pruss = &found[0];
+        assert_eq!(pruss.compatible, "ti,am3356-pruss");
+        assert_eq!((pruss.base, pruss.size), (0x4a30_0000, 0x8_0000));
+        // everything the fixture describes matches the built-in AM335x profile
+        for def in &pruss.regions {
+            assert_eq!(pruss.region(def.region), AM335X.region(0, def.region), "{}", def.region);
+        }
+        let names: Vec<String> = pruss.regions.iter().map(|r| r.region.to_string()).collect();
+        assert_eq!(
+            names,
+            ["dram0", "dram1", "shrdram2", "intc", "pru0-ctrl", "pru1-ctrl", "cfg", "iep0", "pru0-iram", "pru1-iram"]
+        );
+    }
+
+    #[test]
+    fn skips_disabled_and_reads_legacy_reg_names() {
+        let f = Fixture::new();
+        f.prop("ocp", "ranges", b"");
+        f.prop("ocp/pruss@4b200000", "compatible", b"ti,am5728-pruss\0");
+        f.prop("ocp/pruss@4b200000", "status", b"disabled\0");
+        let p = "ocp/pruss@4b280000";
+        f.prop(p, "compatible", b"ti,am5728-pruss\0");
+        f.prop(p, "reg", &be(&[0x4b28_0000, 0x2000, 0x4b28_2000, 0x2000, 0x4b29_0000, 0x8000]));
+        f.prop(p, "reg-names", b"dram0\0dram1\0shrdram2\0");
+
+        let found = discover_at(&f.base()).unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].base, 0x4b28_0000);
+        assert_eq!(found[0].region(Region::SharedRam), Some((0x4b29_0000, 0x8000)));
+    }
+
+    #[test]
+    fn rejects_addresses_past_u64() {
+        let f = Fixture::new();
+        f.prop("", "#address-cells", &be(&[2]));
+        f.prop("", "#size-cells", &be(&[2]));
+        let p = "pruss@ffffffffffff0000";
+        f.prop(p, "compatible", b"ti,am3356-pruss\0");
+        f.prop(p, "reg", &be(&[0xffff_ffff, 0xffff_0000, 0, 0x2_0000, 0xffff_ffff, 0xfff0_0000, 0, 0x2000]));
+        f.prop(p, "reg-names", b"dram0\0dram1\0");
+        assert!(matches!(discover_at(&f.base()), Err(DeviceTreeError::Malformed { .. })));
+
+        let f = Fixture::new();
+        f.prop("", "#address-cells", &be(&[2]));
+        f.prop("", "#size-cells", &be(&[2]));
+        f.prop("ocp", "#address-cells", &be(&[1]));
+        f.prop("ocp", "#size-cells", &be(&[1]));
+        f.prop("ocp", "ranges", &be(&[0, 0xffff_ffff, 0xffff_f000, 0x10_0000]));
+        f.prop("ocp/pruss@4000", "compatible", b"ti,am3356-pruss\0");
+        f.prop("ocp/pruss@4000", "reg", &be(&[0x4000, 0x2000]));
+        assert!(matches!(discover_at(&f.base()), Err(DeviceTreeError::Untranslatable(_))));
+    }
+}
@@ -0,0 +1,474 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use thiserror::Error;
+
+pub mod resource_table;
+pub mod symbols;
+
+pub use resource_table::ResourceTable;
+pub use symbols::{Symbol, SymbolView};
+
+/// ELF machine number for the TI PRU.
+pub const EM_TI_PRU: u16 = 144;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+
+#[derive(Debug, Error)]
+pub enum FirmwareError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("mmio: {0}")]
+    Mmio(#[from] crate::mmio::MmioError),
+    #[error("malformed ELF: {0}")]
+    Malformed(String),
+    #[error("not a PRU image (e_machine {0}, expected {EM_TI_PRU})")]
+    NotPru(u16),
+    #[error("symbol '{0}' not found")]
+    SymbolNotFound(String),
+    #[error("symbol '{0}' is not a variable in a data section")]
+    NotData(String),
+    #[error("symbol '{name}' is {size} bytes, cannot access as {want} bytes")]
+    SymbolSize { name: String, size: u32, want: usize },
+    #[error("{kind:?} segment at {addr:#x} (+{size:#x}) does not fit in {limit:#x} bytes")]
+    DoesNotFit {
+        kind: MemoryKind,
+        addr: u32,
+        size: u32,
+        limit: u32,
+    },
+}
+
+/// PRU memory a loadable segment is placed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    /// Instruction RAM (executable segments).
+    Iram,
+    /// The core's own data RAM and its peer's, addressed back to back from 0.
+    Dram,
+    /// Shared data RAM.
+    SharedDram,
+}
+
+/// Sizes of a PRU core's memories as seen from the core (PRU-local addresses).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PruMemories {
+    pub iram_size: u32,
+    /// Size of one core's data RAM. The peer's DRAM follows at this offset.
+    pub dram_size: u32,
+    pub shared_dram_addr: u32,
+    pub shared_dram_size: u32,
+}
+
+impl PruMemories {
+    /// AM335x PRU-ICSS: 8 KiB IRAM, 8 KiB DRAM per core, 12 KiB shared RAM at 0x10000.
+    pub const AM335X: PruMemories = PruMemories {
+        iram_size: 0x2000,
+        dram_size: 0x2000,
+        shared_dram_addr: 0x1_0000,
+        shared_dram_size: 0x3000,
+    };
+
+    /// AM437x PRU-ICSS1 / AM57xx PRU-ICSS: 12 KiB IRAM, 8 KiB DRAM, 32 KiB shared RAM.
+    pub const AM57XX: PruMemories = PruMemories {
+        iram_size: 0x3000,
+        dram_size: 0x2000,
+        shared_dram_addr: 0x1_0000,
+        shared_dram_size: 0x8000,
+    };
+
+    /// AM64x ICSSG PRU cores: 12 KiB IRAM, 8 KiB DRAM, 64 KiB shared RAM.
+    pub const AM64X: PruMemories = PruMemories {
+        iram_size: 0x3000,
+        dram_size: 0x2000,
+        shared_dram_addr: 0x1_0000,
+        shared_dram_size: 0x1_0000,
+    };
+}
+
+/// A loadable program header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Segment {
+    pub kind: MemoryKind,
+    /// PRU-local load address (`p_paddr`).
+    pub addr: u32,
+    /// Bytes present in the file.
+    pub file_size: u32,
+    /// Bytes occupied in memory (including zero-filled `.bss`).
+    pub mem_size: u32,
+    pub offset: u32,
+    pub flags: u32,
+}
+
+/// A section header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub sh_type: u32,
+    /// `SHF_*` flags.
+    pub flags: u32,
+    pub addr: u32,
+    pub offset: u32,
+    pub size: u32,
+    pub link: u32,
+    pub entsize: u32,
+}
+
+/// A parsed PRU firmware ELF image.
+#[derive(Debug, Clone)]
+pub struct PruFirmware {
+    data: Vec<u8>,
+    entry: u32,
+    segments: Vec<Segment>,
+    sections: Vec<Section>,
+}
+
+impl PruFirmware {
+    /// Read and parse the ELF at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
+        Self::parse(fs::read(path)?)
+    }
+
+    /// Parse an in-memory ELF image.
+    pub fn parse(data: Vec<u8>) -> Result<Self> {
+        if data.len() < 52 || &data[..4] != b"\x7fELF" {
+            return Err(malformed("missing ELF header"));
+        }
+        if data[4] != 1 || data[5] != 1 {
+            return Err(malformed("not a 32-bit little-endian ELF"));
+        }
+        let machine = read_u16(&data, 18)?;
+        if machine != EM_TI_PRU {
+            return Err(FirmwareError::NotPru(machine));
+        }
+        let entry = read_u32(&data, 24)?;
+        let phoff = read_u32(&data, 28)? as usize;
+        let phentsize = read_u16(&data, 42)? as usize;
+        let phnum = read_u16(&data, 44)? as usize;
+
+        let mut segments = Vec::new();
+        for i in 0..phnum {
+            let ph = table_entry(phoff, i, phentsize).ok_or_else(|| malformed("program header out of range"))?;
+            if read_u32(&data, ph)? != PT_LOAD {
+                continue;
+            }
+            let offset = read_u32(&data, ph + 4)?;
+            let addr = read_u32(&data, ph + 12)?;
+            let file_size = read_u32(&data, ph + 16)?;
+            let mem_size = read_u32(&data, ph + 20)?;
+            let flags = read_u32(&data, ph + 24)?;
+            if (offset as usize).checked_add(file_size as usize).is_none_or(|end| end > data.len()) {
+                return Err(malformed("segment extends past end of file"));
+            }
+            let kind = if flags & PF_X != 0 { MemoryKind::Iram } else { MemoryKind::Dram };
+            segments.push(Segment { kind, addr, file_size, mem_size, offset, flags });
+        }
+
+        let sections = parse_sections(&data)?;
+        Ok(PruFirmware { data, entry, segments, sections })
+    }
+
+    /// Entry point (`e_entry`: an IRAM byte address, as written by the linker).
+    pub fn entry(&self) -> u32 {
+        self.entry
+    }
+
+    /// Loadable segments classified as IRAM or DRAM.
+    pub fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// File contents of a loadable segment.
+    pub fn segment_data(&self, seg: &Segment) -> &[u8] {
+        &self.data[seg.offset as usize..seg.offset as usize + seg.file_size as usize]
+    }
+
+    /// Section headers (empty when the image was stripped of them).
+    pub fn sections(&self) -> &[Section] {
+        &self.sections
+    }
+
+    /// Contents of the section called `name`, if present.
+    pub fn section_data(&self, name: &str) -> Option<&[u8]> {
+        let s = self.sections.iter().find(|s| s.name == name)?;
+        self.data.get(s.offset as usize..(s.offset as usize).checked_add(s.size as usize)?)
+    }
+
+    /// Read `len` bytes of initialised data loaded at PRU address `addr`
+    /// from the DRAM segments of the image.
+    pub fn read_loaded(&self, addr: u32, len: usize) -> Option<&[u8]> {
+        self.segments.iter().filter(|s| s.kind != MemoryKind::Iram).find_map(|s| {
+            let rel = addr.checked_sub(s.addr)? as usize;
+            if rel.checked_add(len)? > s.file_size as usize {
+                return None;
+            }
+            let start = s.offset as usize + rel;
+            self.data.get(start..start + len)
+        })
+    }
+
+    /// Symbols from the `.symtab` section (empty when stripped).
+    pub fn symbols(&self) -> Result<Vec<Symbol>> {
+        symbols::parse(self)
+    }
+
+    /// Look up a symbol by name.
+    pub fn symbol(&self, name: &str) -> Result<Symbol> {
+        self.symbols()?
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| FirmwareError::SymbolNotFound(name.to_string()))
+    }
+
+    /// Decode the `.resource_table` section, if present.
+    pub fn resource_table(&self) -> Result<Option<ResourceTable>> {
+        match self.section_data(resource_table::SECTION) {
+            Some(bytes) => ResourceTable::parse(bytes, Some(self)).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// The raw image bytes.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    /// Segments placed in `mem`, with data segments in the shared RAM window
+    /// reported as `SharedDram`.
+    pub fn placed_segments(&self, mem: &PruMemories) -> Vec<Segment> {
+        self.segments
+            .iter()
+            .map(|s| {
+                let shared = s.addr >= mem.shared_dram_addr;
+                let kind = match s.kind {
+                    MemoryKind::Dram if shared => MemoryKind::SharedDram,
+                    k => k,
+                };
+                Segment { kind, ..*s }
+            })
+            .collect()
+    }
+
+    /// Check every loadable segment fits the memories of the target core.
+    pub fn validate(&self, mem: &PruMemories) -> Result<()> {
+        for s in self.placed_segments(mem) {
+            let (start, limit) = match s.kind {
+                MemoryKind::Iram => (0, mem.iram_size),
+                MemoryKind::Dram => (0, 2 * mem.dram_size),
+                MemoryKind::SharedDram => (mem.shared_dram_addr, mem.shared_dram_size),
+            };
+            let end = (s.addr as u64 - start as u64) + s.mem_size as u64;
+            if end > limit as u64 {
+                return Err(FirmwareError::DoesNotFit {
+                    kind: s.kind,
+                    addr: s.addr,
+                    size: s.mem_size,
+                    limit,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_sections(data: &[u8]) -> Result<Vec<Section>> {
+    let shoff = read_u32(data, 32)? as usize;
+    let shentsize = read_u16(data, 46)? as usize;
+    let shnum = read_u16(data, 48)? as usize;
+    let shstrndx = read_u16(data, 50)? as usize;
+    if shoff == 0 || shnum == 0 {
+        return Ok(Vec::new());
+    }
+
+    let mut raw = Vec::with_capacity(shnum);
+    for i in 0..shnum {
+        let sh = table_entry(shoff, i, shentsize).ok_or_else(|| malformed("section header out of range"))?;
+        raw.push((
+            read_u32(data, sh)?,
+            Section {
+                name: String::new(),
+                sh_type: read_u32(data, sh + 4)?,
+                flags: read_u32(data, sh + 8)?,
+                addr: read_u32(data, sh + 12)?,
+                offset: read_u32(data, sh + 16)?,
+                size: read_u32(data, sh + 20)?,
+                link: read_u32(data, sh + 24)?,
+                entsize: read_u32(data, sh + 36)?,
+            },
+        ));
+    }
+
+    let strtab = raw.get(shstrndx).map(|(_, s)| s.offset as usize).unwrap_or(0);
+    Ok(raw
+        .into_iter()
+        .map(|(name_off, mut s)| {
+            s.name = read_cstr(data, strtab.saturating_add(name_off as usize));
+            s
+        })
+        .collect())
+}
+
+/// Offset of entry `i` of a header table at `off`, or `None` on overflow.
+pub(crate) fn table_entry(off: usize, i: usize, entsize: usize) -> Option<usize> {
+    i.checked_mul(entsize)?.checked_add(off)
+}
+
+/// Read a NUL-terminated string at `off`; empty when out of range.
+pub(crate) fn read_cstr(data: &[u8], off: usize) -> String {
+    let bytes = data.get(off..).unwrap_or(&[]);
+    let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+pub(crate) fn malformed(msg: &str) -> FirmwareError {
+    FirmwareError::Malformed(msg.to_string())
+}
+
+pub(crate) fn read_u16(data: &[u8], off: usize) -> Result<u16> {
+    data.get(off..off.checked_add(2).ok_or_else(|| malformed("truncated"))?)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+pub(crate) fn read_u32(data: &[u8], off: usize) -> Result<u32> {
+    data.get(off..off.checked_add(4).ok_or_else(|| malformed("truncated"))?)
+        .map(|b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+        .ok_or_else(|| malformed("truncated"))
+}
+
+pub type Result<T> = std::result::Result<T, FirmwareError>;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    type SectionSpec = (&'static str, u32, u32, Vec<u8>, u32, u32);
+
+    /// Minimal ELF32 LE writer for tests: program headers are
+    /// `(flags, paddr, bytes, mem_size)`, sections are
+    /// `(name, sh_type, flags, bytes, link, entsize)`.
+    pub(crate) struct ElfBuilder {
+        pub machine: u16,
+        pub entry: u32,
+        pub segments: Vec<(u32, u32, Vec<u8>, u32)>,
+        pub sections: Vec<SectionSpec>,
+    }
+
+    impl ElfBuilder {
+        pub(crate) fn pru() -> Self {
+            ElfBuilder { machine: EM_TI_PRU, entry: 0, segments: Vec::new(), sections: Vec::new() }
+        }
+
+        pub(crate) fn build(&self) -> Vec<u8> {
+            let phoff = 52usize;
+            let mut data_off = phoff + 32 * self.segments.len();
+            let mut out = vec![0u8; data_off];
+            out[..4].copy_from_slice(b"\x7fELF");
+            out[4] = 1;
+            out[5] = 1;
+            out[6] = 1;
+            out[16..18].copy_from_slice(&2u16.to_le_bytes());
+            out[18..20].copy_from_slice(&self.machine.to_le_bytes());
+            out[20..24].copy_from_slice(&1u32.to_le_bytes());
+            out[24..28].copy_from_slice(&self.entry.to_le_bytes());
+            out[28..32].copy_from_slice(&(phoff as u32).to_le_bytes());
+            out[40..42].copy_from_slice(&52u16.to_le_bytes());
+            out[42..44].copy_from_slice(&32u16.to_le_bytes());
+            out[44..46].copy_from_slice(&(self.segments.len() as u16).to_le_bytes());
+            for (i, (flags, addr, bytes, mem_size)) in self.segments.iter().enumerate() {
+                let ph = phoff + 32 * i;
+                let fields = [PT_LOAD, data_off as u32, *addr, *addr, bytes.len() as u32, *mem_size, *flags, 4];
+                for (j, f) in fields.iter().enumerate() {
+                    out[ph + 4 * j..ph + 4 * j + 4].copy_from_slice(&f.to_le_bytes());
+                }
+                data_off += bytes.len();
+            }
+            for (_, _, bytes, _) in &self.segments {
+                out.extend_from_slice(bytes);
+            }
+            if self.sections.is_empty() {
+                return out;
+            }
+
+            // section contents, then .shstrtab, then the header table
+            let mut shstrtab = vec![0u8];
+            let mut headers = vec![[0u32; 10]];
+            for (name, sh_type, flags, bytes, link, entsize) in &self.sections {
+                let name_off = shstrtab.len() as u32;
+                shstrtab.extend_from_slice(name.as_bytes());
+                shstrtab.push(0);
+                headers.push([name_off, *sh_type, *flags, 0, out.len() as u32, bytes.len() as u32, *link, 0, 4, *entsize]);
+                out.extend_from_slice(bytes);
+            }
+            let name_off = shstrtab.len() as u32;
+            shstrtab.extend_from_slice(b".shstrtab\0");
+            headers.push([name_off, 3, 0, 0, out.len() as u32, shstrtab.len() as u32, 0, 0, 1, 0]);
+            out.extend_from_slice(&shstrtab);
+
+            let shoff = out.len() as u32;
+            for h in &headers {
+                for f in h {
+                    out.extend_from_slice(&f.to_le_bytes());
+                }
+            }
+            out[32..36].copy_from_slice(&shoff.to_le_bytes());
+            out[46..48].copy_from_slice(&40u16.to_le_bytes());
+            out[48..50].copy_from_slice(&(headers.len() as u16).to_le_bytes());
+            out[50..52].copy_from_slice(&((headers.len() - 1) as u16).to_le_bytes());
+            out
+        }
+    }
+
+    #[test]
+    fn parses_segments_and_entry() {
+        let mut b = ElfBuilder::pru();
+        b.entry = 0x40;
+        b.segments.push((PF_X | 4, 0, vec![0; 0x100], 0x100));
+        b.segments.push((6, 0, vec![1; 0x10], 0x200));
+        b.segments.push((6, 0x1_0000, vec![2; 0x10], 0x10));
+        let fw = PruFirmware::parse(b.build()).unwrap();
+
+        assert_eq!(fw.entry(), 0x40);
+        let kinds: Vec<_> = fw.placed_segments(&PruMemories::AM335X).iter().map(|s| s.kind).collect();
+        assert_eq!(kinds, vec![MemoryKind::Iram, MemoryKind::Dram, MemoryKind::SharedDram]);
+        assert_eq!(fw.segments()[1].mem_size, 0x200);
+        fw.validate(&PruMemories::AM335X).unwrap();
+    }
+
+    #[test]
+    fn rejects_wrong_machine_and_oversize() {
+        let mut b = ElfBuilder::pru();
+        b.machine = 40; // ARM
+        assert!(matches!(PruFirmware::parse(b.build()), Err(FirmwareError::NotPru(40))));
+
+        let mut b = ElfBuilder::pru();
+        b.segments.push((PF_X, 0x1f00, vec![0; 4], 0x200));
+        let fw = PruFirmware::parse(b.build()).unwrap();
+        match fw.validate(&PruMemories::AM335X) {
+            Err(FirmwareError::DoesNotFit { kind: MemoryKind::Iram, limit: 0x2000, .. }) => {}
+            other => panic!("expected DoesNotFit, got {:?}", other),
+        }
+
+        assert!(matches!(PruFirmware::parse(b"\x7fELF".to_vec()), Err(FirmwareError::Malformed(_))));
+    }
+
+    #[test]
+    fn rejects_out_of_range_offsets() {
+        let mut b = ElfBuilder::pru();
+        b.segments.push((6, 0, vec![1; 0x10], 0x10));
+        let good = b.build();
+
+        // segment file size running past the end of the image (and of u32)
+        let mut data = good.clone();
+        data[52 + 16..52 + 20].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(PruFirmware::parse(data), Err(FirmwareError::Malformed(_))));
+
+        // program header table at the very top of the address space
+        let mut data = good;
+        data[28..32].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(matches!(PruFirmware::parse(data), Err(FirmwareError::Malformed(_))));
+        assert_eq!(table_entry(usize::MAX, 1, 32), None);
+    }
+}
@@ -0,0 +1,408 @@
+//! Decoding of the remoteproc `.resource_table` section.
+//!
+//! Layouts follow `include/linux/remoteproc.h` and the TI PRU software
+//! support package (`rsc_types.h`) for the custom interrupt resource.
+
+use std::fmt;
+
+use thiserror::Error;
+
+use super::{malformed, read_cstr, read_u16, read_u32, PruFirmware, Result};
+use crate::rpmsg::Rpmsg;
+
+/// Name of the ELF section holding the resource table.
+pub const SECTION: &str = ".resource_table";
+
+const RSC_CARVEOUT: u32 = 0;
+const RSC_DEVMEM: u32 = 1;
+const RSC_TRACE: u32 = 2;
+const RSC_VDEV: u32 = 3;
+/// TI `TYPE_PRELOAD_VENDOR` / `TYPE_POSTLOAD_VENDOR` (older trees: `TYPE_CUSTOM`).
+const RSC_TI_PRELOAD_VENDOR: u32 = 4;
+const RSC_TI_POSTLOAD_VENDOR: u32 = 5;
+/// TI custom resource sub-type for PRU INTC mapping.
+const TI_TYPE_PRU_INTS: u32 = 1;
+
+/// Virtio device id for rpmsg.
+pub const VIRTIO_ID_RPMSG: u32 = 7;
+/// rpmsg virtio feature bit: name service announcements.
+pub const VIRTIO_RPMSG_F_NS: u32 = 0;
+
+/// A decoded resource table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceTable {
+    pub version: u32,
+    pub entries: Vec<Resource>,
+}
+
+/// One resource table entry.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Resource {
+    Carveout(Memory),
+    Devmem(Memory),
+    Trace(Trace),
+    Vdev(Vdev),
+    PruInterrupts(PruInterrupts),
+    Unknown { kind: u32, offset: u32 },
+}
+
+/// `fw_rsc_carveout` / `fw_rsc_devmem`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    pub da: u32,
+    pub pa: u32,
+    pub len: u32,
+    pub flags: u32,
+    pub name: String,
+}
+
+/// `fw_rsc_trace`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trace {
+    pub da: u32,
+    pub len: u32,
+    pub name: String,
+}
+
+/// `fw_rsc_vdev` and its vrings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vdev {
+    pub id: u32,
+    pub notify_id: u32,
+    pub dfeatures: u32,
+    pub gfeatures: u32,
+    pub config_len: u32,
+    pub status: u8,
+    pub vrings: Vec<Vring>,
+}
+
+impl Vdev {
+    /// True for an rpmsg virtio device.
+    pub fn is_rpmsg(&self) -> bool {
+        self.id == VIRTIO_ID_RPMSG
+    }
+
+    /// True when the device advertises rpmsg name service.
+    pub fn has_name_service(&self) -> bool {
+        self.dfeatures & (1 << VIRTIO_RPMSG_F_NS) != 0
+    }
+}
+
+/// `fw_rsc_vdev_vring`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vring {
+    pub da: u32,
+    pub align: u32,
+    pub num: u32,
+    pub notify_id: u32,
+    pub pa: u32,
+}
+
+/// TI `fw_rsc_custom_ints`: system event to channel to host interrupt mapping.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PruInterrupts {
+    pub version: u16,
+    /// Host interrupt for each INTC channel (0xff = unused).
+    pub channel_host: [u8; 10],
+    /// PRU address of the `(event, channel)` map.
+    pub event_channel_addr: u32,
+    /// `(event, channel)` pairs, when the map could be read from the image.
+    pub event_channel: Vec<(u8, u8)>,
+}
+
+/// A consistency problem found by `ResourceTable::check`.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum Issue {
+    #[error("unsupported resource table version {0}")]
+    UnsupportedVersion(u32),
+    #[error("no rpmsg vdev resource")]
+    NoRpmsgVdev,
+    #[error("vdev {notify_id} has {found} vrings, rpmsg expects {expected}")]
+    VringCount { notify_id: u32, found: usize, expected: usize },
+    #[error("vdev {notify_id} vring {index} has {num} buffers (must be a non-zero power of two)")]
+    VringSize { notify_id: u32, index: usize, num: u32 },
+    #[error("{kind} '{name}' has zero length")]
+    ZeroLength { kind: &'static str, name: String },
+    #[error("notify id {0} used more than once")]
+    DuplicateNotifyId(u32),
+}
+
+impl ResourceTable {
+    /// Decode a resource table. `image` is used to resolve data the table
+    /// points to elsewhere in the firmware (the PRU interrupt map).
+    pub fn parse(bytes: &[u8], image: Option<&PruFirmware>) -> Result<Self> {
+        let version = read_u32(bytes, 0)?;
+        let num = read_u32(bytes, 4)? as usize;
+        let mut entries = Vec::with_capacity(num.min(64));
+        for i in 0..num {
+            let offset = read_u32(bytes, 16 + 4 * i)?;
+            entries.push(parse_entry(bytes, offset as usize, image)?);
+        }
+        Ok(ResourceTable { version, entries })
+    }
+
+    /// All vdev entries.
+    pub fn vdevs(&self) -> impl Iterator<Item = &Vdev> {
+        self.entries.iter().filter_map(|e| match e {
+            Resource::Vdev(v) => Some(v),
+            _ => None,
+        })
+    }
+
+    /// All trace entries.
+    pub fn traces(&self) -> impl Iterator<Item = &Trace> {
+        self.entries.iter().filter_map(|e| match e {
+            Resource::Trace(t) => Some(t),
+            _ => None,
+        })
+    }
+
+    /// Check the table for problems the kernel or `Rpmsg` would trip over.
+    /// When `expect_rpmsg` is set a missing rpmsg vdev is reported.
+    pub fn check(&self, expect_rpmsg: bool) -> Vec<Issue> {
+        let mut issues = Vec::new();
+        if self.version != 1 {
+            issues.push(Issue::UnsupportedVersion(self.version));
+        }
+        if expect_rpmsg && !self.vdevs().any(Vdev::is_rpmsg) {
+            issues.push(Issue::NoRpmsgVdev);
+        }
+
+        let mut notify_ids = Vec::new();
+        for v in self.vdevs() {
+            notify_ids.push(v.notify_id);
+            if v.is_rpmsg() && v.vrings.len() != Rpmsg::VRING_COUNT {
+                issues.push(Issue::VringCount {
+                    notify_id: v.notify_id,
+                    found: v.vrings.len(),
+                    expected: Rpmsg::VRING_COUNT,
+                });
+            }
+            for (index, r) in v.vrings.iter().enumerate() {
+                notify_ids.push(r.notify_id);
+                if !r.num.is_power_of_two() {
+                    issues.push(Issue::VringSize { notify_id: v.notify_id, index, num: r.num });
+                }
+            }
+        }
+        notify_ids.sort_unstable();
+        for w in notify_ids.windows(2) {
+            if w[0] == w[1] && !issues.contains(&Issue::DuplicateNotifyId(w[0])) {
+                issues.push(Issue::DuplicateNotifyId(w[0]));
+            }
+        }
+
+        for e in &self.entries {
+            let (kind, name, len) = match e {
+                Resource::Carveout(m) => ("carveout", &m.name, m.len),
+                Resource::Devmem(m) => ("devmem", &m.name, m.len),
+                Resource::Trace(t) => ("trace", &t.name, t.len),
+                _ => continue,
+            };
+            if len == 0 {
+                issues.push(Issue::ZeroLength { kind, name: name.clone() });
+            }
+        }
+        issues
+    }
+}
+
+fn parse_entry(bytes: &[u8], off: usize, image: Option<&PruFirmware>) -> Result<Resource> {
+    let kind = read_u32(bytes, off)?;
+    let body = off + 4;
+    let res = match kind {
+        RSC_CARVEOUT | RSC_DEVMEM => {
+            let m = Memory {
+                da: read_u32(bytes, body)?,
+                pa: read_u32(bytes, body + 4)?,
+                len: read_u32(bytes, body + 8)?,
+                flags: read_u32(bytes, body + 12)?,
+                name: read_name(bytes, body + 20)?,
+            };
+            if kind == RSC_CARVEOUT {
+                Resource::Carveout(m)
+            } else {
+                Resource::Devmem(m)
+            }
+        }
+        RSC_TRACE => Resource::Trace(Trace {
+            da: read_u32(bytes, body)?,
+            len: read_u32(bytes, body + 4)?,
+            name: read_name(bytes, body + 12)?,
+        }),
+        RSC_VDEV => {
+            let num_vrings = *bytes.get(body + 21).ok_or_else(|| malformed("truncated vdev"))? as usize;
+            let mut vrings = Vec::with_capacity(num_vrings);
+            for i in 0..num_vrings {
+                let r = body + 24 + 20 * i;
+                vrings.push(Vring {
+                    da: read_u32(bytes, r)?,
+                    align: read_u32(bytes, r + 4)?,
+                    num: read_u32(bytes, r + 8)?,
+                    notify_id: read_u32(bytes, r + 12)?,
+                    pa: read_u32(bytes, r + 16)?,
+                });
+            }
+            Resource::Vdev(Vdev {
+                id: read_u32(bytes, body)?,
+                notify_id: read_u32(bytes, body + 4)?,
+                dfeatures: read_u32(bytes, body + 8)?,
+                gfeatures: read_u32(bytes, body + 12)?,
+                config_len: read_u32(bytes, body + 16)?,
+                status: bytes[body + 20],
+                vrings,
+            })
+        }
+        RSC_TI_PRELOAD_VENDOR | RSC_TI_POSTLOAD_VENDOR if read_u32(bytes, body)? == TI_TYPE_PRU_INTS => {
+            // sub_type, rsc_size, then fw_rsc_custom_ints
+            let ints = body + 8;
+            let mut channel_host = [0u8; 10];
+            channel_host.copy_from_slice(bytes.get(ints + 2..ints + 12).ok_or_else(|| malformed("truncated interrupt resource"))?);
+            let num_evts = read_u32(bytes, ints + 12)? as usize;
+            let event_channel_addr = read_u32(bytes, ints + 16)?;
+            let event_channel = image
+                .and_then(|fw| fw.read_loaded(event_channel_addr, num_evts.checked_mul(2)?))
+                .map(|m| m.chunks_exact(2).map(|p| (p[0], p[1])).collect())
+                .unwrap_or_default();
+            Resource::PruInterrupts(PruInterrupts {
+                version: read_u16(bytes, ints)?,
+                channel_host,
+                event_channel_addr,
+                event_channel,
+            })
+        }
+        _ => Resource::Unknown { kind, offset: off as u32 },
+    };
+    Ok(res)
+}
+
+fn read_name(bytes: &[u8], off: usize) -> Result<String> {
+    let field = bytes.get(off..off + 32).ok_or_else(|| malformed("truncated resource name"))?;
+    Ok(read_cstr(field, 0))
+}
+
+impl fmt::Display for ResourceTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "resource table v{} ({} entries)", self.version, self.entries.len())?;
+        for (i, e) in self.entries.iter().enumerate() {
+            write!(f, "  [{}] ", i)?;
+            match e {
+                Resource::Carveout(m) | Resource::Devmem(m) => {
+                    let kind = if matches!(e, Resource::Carveout(_)) { "carveout" } else { "devmem" };
+                    writeln!(
+                        f,
+                        "{} '{}' da={:#010x} pa={:#010x} len={:#x} flags={:#x}",
+                        kind, m.name, m.da, m.pa, m.len, m.flags
+                    )?;
+                }
+                Resource::Trace(t) => writeln!(f, "trace '{}' da={:#010x} len={:#x}", t.name, t.da, t.len)?,
+                Resource::Vdev(v) => {
+                    writeln!(
+                        f,
+                        "vdev id={}{} notifyid={} dfeatures={:#x} config_len={} vrings={}",
+                        v.id,
+                        if v.is_rpmsg() { " (rpmsg)" } else { "" },
+                        v.notify_id,
+                        v.dfeatures,
+                        v.config_len,
+                        v.vrings.len()
+                    )?;
+                    for (j, r) in v.vrings.iter().enumerate() {
+                        writeln!(
+                            f,
+                            "      vring{} da={:#010x} align={} num={} notifyid={}",
+                            j, r.da, r.align, r.num, r.notify_id
+                        )?;
+                    }
+                }
+                Resource::PruInterrupts(p) => {
+                    writeln!(f, "pru interrupts v{} channel_host={:?}", p.version, p.channel_host)?;
+                    for (evt, ch) in &p.event_channel {
+                        writeln!(f, "      event {} -> channel {}", evt, ch)?;
+                    }
+                }
+                Resource::Unknown { kind, offset } => writeln!(f, "unknown type {} at {:#x}", kind, offset)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firmware::tests::ElfBuilder;
+
+    fn words(out: &mut Vec<u8>, ws: &[u32]) {
+        for w in ws {
+            out.extend_from_slice(&w.to_le_bytes());
+        }
+    }
+
+    fn name(out: &mut Vec<u8>, s: &str) {
+        let mut n = [0u8; 32];
+        n[..s.len()].copy_from_slice(s.as_bytes());
+        out.extend_from_slice(&n);
+    }
+
+    /// Table with a vdev (given vring sizes), a trace and a TI interrupt map
+    /// pointing at `map_addr`.
+    fn table(vring_nums: &[u32], map_addr: u32) -> Vec<u8> {
+        let mut vdev = Vec::new();
+        words(&mut vdev, &[RSC_VDEV, VIRTIO_ID_RPMSG, 0, 1, 0, 0]);
+        vdev.extend_from_slice(&[0, vring_nums.len() as u8, 0, 0]);
+        for (i, n) in vring_nums.iter().enumerate() {
+            words(&mut vdev, &[0, 16, *n, i as u32 + 1, 0]);
+        }
+        let mut trace = Vec::new();
+        words(&mut trace, &[RSC_TRACE, 0x1000, 0x400, 0]);
+        name(&mut trace, "trace:pru0");
+        let mut ints = Vec::new();
+        words(&mut ints, &[RSC_TI_POSTLOAD_VENDOR, TI_TYPE_PRU_INTS, 20]);
+        ints.extend_from_slice(&0x0001u16.to_le_bytes());
+        ints.extend_from_slice(&[0, 1, 2, 3, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+        words(&mut ints, &[2, map_addr]);
+
+        let mut out = Vec::new();
+        let hdr = 16 + 4 * 3;
+        words(&mut out, &[1, 3, 0, 0]);
+        words(&mut out, &[hdr, hdr + vdev.len() as u32, hdr + (vdev.len() + trace.len()) as u32]);
+        out.extend(vdev);
+        out.extend(trace);
+        out.extend(ints);
+        out
+    }
+
+    #[test]
+    fn decodes_entries_from_image() {
+        let mut b = ElfBuilder::pru();
+        b.segments.push((6, 0x100, vec![16, 2, 17, 3], 4));
+        b.sections.push((SECTION, 1, 2, table(&[16, 16], 0x100), 0, 0));
+        let fw = PruFirmware::parse(b.build()).unwrap();
+        let rt = fw.resource_table().unwrap().unwrap();
+
+        assert_eq!(rt.version, 1);
+        let vdev = rt.vdevs().next().unwrap();
+        assert!(vdev.is_rpmsg() && vdev.has_name_service());
+        assert_eq!(vdev.vrings.len(), 2);
+        assert_eq!(vdev.vrings[1].notify_id, 2);
+        assert_eq!(rt.traces().next().unwrap().name, "trace:pru0");
+        match &rt.entries[2] {
+            Resource::PruInterrupts(p) => {
+                assert_eq!(p.channel_host[3], 3);
+                assert_eq!(p.event_channel, vec![(16, 2), (17, 3)]);
+            }
+            other => panic!("expected interrupts, got {:?}", other),
+        }
+        assert!(rt.check(true).is_empty());
+        assert!(rt.to_string().contains("event 17 -> channel 3"));
+    }
+
+    #[test]
+    fn check_reports_vring_problems() {
+        let rt = ResourceTable::parse(&table(&[16, 12, 16], 0), None).unwrap();
+        let issues = rt.check(true);
+        assert!(issues.contains(&Issue::VringCount { notify_id: 0, found: 3, expected: 2 }));
+        assert!(issues.contains(&Issue::VringSize { notify_id: 0, index: 1, num: 12 }));
+    }
+}
@@ -0,0 +1,181 @@
+//! ELF symbol table lookup and access to firmware variables through `Mmio`.
+
+use std::collections::HashMap;
+use std::mem::size_of;
+
+use super::{read_cstr, read_u16, read_u32, table_entry, FirmwareError, PruFirmware, Result};
+use crate::bus::MemoryBus;
+use crate::mmio::{Mmio, MmioValue};
+
+const SHT_SYMTAB: u32 = 2;
+const SHF_ALLOC: u32 = 2;
+const SHF_EXECINSTR: u32 = 4;
+const STT_OBJECT: u8 = 1;
+const STT_SECTION: u8 = 3;
+const STT_FILE: u8 = 4;
+
+/// A named symbol from the firmware's symbol table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    /// PRU-local address.
+    pub value: u32,
+    pub size: u32,
+    /// Name of the section the symbol is defined in, if any.
+    pub section: Option<String>,
+    /// `STT_*` type from `st_info` (`1` for variables, `2` for functions).
+    pub sym_type: u8,
+    /// Whether the symbol is a variable in an allocated, non-executable
+    /// section, i.e. something `SymbolView` can address in data RAM.
+    pub is_data: bool,
+}
+
+pub(super) fn parse(fw: &PruFirmware) -> Result<Vec<Symbol>> {
+    let data = fw.data();
+    let sections = fw.sections();
+    let Some(symtab) = sections.iter().find(|s| s.sh_type == SHT_SYMTAB) else {
+        return Ok(Vec::new());
+    };
+    let strtab = sections.get(symtab.link as usize).map(|s| s.offset as usize).unwrap_or(0);
+    let entsize = if symtab.entsize == 0 { 16 } else { symtab.entsize as usize };
+
+    let mut out = Vec::new();
+    for i in 0..symtab.size as usize / entsize {
+        let sym = table_entry(symtab.offset as usize, i, entsize).ok_or_else(|| super::malformed("truncated symtab"))?;
+        let info = *sym.checked_add(12).and_then(|at| data.get(at)).ok_or_else(|| super::malformed("truncated symtab"))?;
+        let name = read_cstr(data, strtab.saturating_add(read_u32(data, sym)? as usize));
+        let sym_type = info & 0xf;
+        if name.is_empty() || matches!(sym_type, STT_SECTION | STT_FILE) {
+            continue;
+        }
+        let shndx = read_u16(data, sym + 14)? as usize;
+        let section = sections.get(shndx).filter(|_| shndx != 0);
+        let in_data = section.is_some_and(|s| s.flags & (SHF_ALLOC | SHF_EXECINSTR) == SHF_ALLOC);
+        out.push(Symbol {
+            name,
+            value: read_u32(data, sym + 4)?,
+            size: read_u32(data, sym + 8)?,
+            section: section.map(|s| s.name.clone()),
+            sym_type,
+            is_data: sym_type == STT_OBJECT && in_data,
+        });
+    }
+    Ok(out)
+}
+
+/// Firmware variables addressed by name on a mapping of the core's DRAM.
+///
+/// Symbol values are PRU-local data addresses; `dram_base` is the physical
+/// address the core sees as local address 0 (its own data RAM). Only
+/// variables in data sections resolve; functions and other symbols are
+/// refused with `NotData`. Works on any `MemoryBus`, so a `MockBus` can stand
+/// in for the mapping.
+pub struct SymbolView<'a, B: MemoryBus + ?Sized = Mmio> {
+    bus: &'a mut B,
+    symbols: HashMap<String, Symbol>,
+    dram_base: u64,
+}
+
+impl<'a, B: MemoryBus + ?Sized> SymbolView<'a, B> {
+    pub fn new(bus: &'a mut B, fw: &PruFirmware, dram_base: u64) -> Result<Self> {
+        let symbols = fw.symbols()?.into_iter().map(|s| (s.name.clone(), s)).collect();
+        Ok(SymbolView { bus, symbols, dram_base })
+    }
+
+    /// Physical address of variable `name`.
+    pub fn address(&self, name: &str) -> Result<u64> {
+        Ok(self.dram_base + self.lookup(name)?.value as u64)
+    }
+
+    /// Read the firmware variable `name`.
+    pub fn read_symbol<T: MmioValue>(&self, name: &str) -> Result<T> {
+        let addr = self.checked_address::<T>(name)?;
+        Ok(T::read_from(&*self.bus, addr)?)
+    }
+
+    /// Write the firmware variable `name`.
+    pub fn write_symbol<T: MmioValue>(&mut self, name: &str, val: T) -> Result<()> {
+        let addr = self.checked_address::<T>(name)?;
+        Ok(val.write_to(&mut *self.bus, addr)?)
+    }
+
+    fn lookup(&self, name: &str) -> Result<&Symbol> {
+        match self.symbols.get(name) {
+            Some(sym) if sym.is_data => Ok(sym),
+            Some(_) => Err(FirmwareError::NotData(name.to_string())),
+            None => Err(FirmwareError::SymbolNotFound(name.to_string())),
+        }
+    }
+
+    /// Resolve `name`, refusing accesses wider than the symbol (when its size is known).
+    fn checked_address<T>(&self, name: &str) -> Result<u64> {
+        let sym = self.lookup(name)?;
+        if sym.size != 0 && (sym.size as usize) < size_of::<T>() {
+            return Err(FirmwareError::SymbolSize {
+                name: name.to_string(),
+                size: sym.size,
+                want: size_of::<T>(),
+            });
+        }
+        Ok(self.dram_base + sym.value as u64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::firmware::tests::ElfBuilder;
+
+    fn sample_fw() -> PruFirmware {
+        let strtab = b"\0sample_count\0flag\0main\0".to_vec();
+        let mut symtab = vec![0u8; 16];
+        let syms = [(1u32, 0x100u32, 4u32, 0x11u8, 2u16), (14, 0x104, 1, 0x11, 2), (19, 0x20, 8, 0x12, 1)];
+        for (name, value, size, info, shndx) in syms {
+            symtab.extend_from_slice(&name.to_le_bytes());
+            symtab.extend_from_slice(&value.to_le_bytes());
+            symtab.extend_from_slice(&size.to_le_bytes());
+            symtab.extend_from_slice(&[info, 0]);
+            symtab.extend_from_slice(&shndx.to_le_bytes());
+        }
+        let mut b = ElfBuilder::pru();
+        b.sections.push((".text", 1, SHF_ALLOC | SHF_EXECINSTR, vec![0; 0x40], 0, 0));
+        b.sections.push((".data", 1, SHF_ALLOC | 1, vec![0; 8], 0, 0));
+        b.sections.push((".symtab", SHT_SYMTAB, 0, symtab, 4, 16));
+        b.sections.push((".strtab", 3, 0, strtab, 0, 0));
+        PruFirmware::parse(b.build()).unwrap()
+    }
+
+    #[test]
+    fn resolves_symbols() {
+        let fw = sample_fw();
+        let syms = fw.symbols().unwrap();
+        assert_eq!(syms.len(), 3);
+        let s = fw.symbol("sample_count").unwrap();
+        assert_eq!((s.value, s.size), (0x100, 4));
+        assert_eq!(s.section.as_deref(), Some(".data"));
+        assert!(s.is_data);
+        let main = fw.symbol("main").unwrap();
+        assert_eq!((main.sym_type, main.section.as_deref(), main.is_data), (2, Some(".text"), false));
+        assert!(matches!(fw.symbol("missing"), Err(FirmwareError::SymbolNotFound(_))));
+    }
+
+    #[test]
+    fn view_reads_and_writes_through_bus() {
+        use crate::bus::{AccessKind, MockBus};
+
+        let fw = sample_fw();
+        let mut bus = MockBus::new(0x4a30_0000, 0x2000);
+        bus.memory_mut()[0x100..0x104].copy_from_slice(&7u32.to_le_bytes());
+        let mut view = SymbolView::new(&mut bus, &fw, 0x4a30_0000).unwrap();
+        assert_eq!(view.read_symbol::<u32>("sample_count").unwrap(), 7);
+        view.write_symbol::<u8>("flag", 1).unwrap();
+        assert!(matches!(view.write_symbol::<u32>("flag", 1), Err(FirmwareError::SymbolSize { .. })));
+        // code symbols are IRAM addresses, not data RAM
+        assert!(matches!(view.address("main"), Err(FirmwareError::NotData(_))));
+        assert!(matches!(view.read_symbol::<u32>("main"), Err(FirmwareError::NotData(_))));
+
+        let writes: Vec<_> = bus.accesses().into_iter().filter(|a| a.kind == AccessKind::Write).collect();
+        assert_eq!(writes.len(), 1);
+        assert_eq!((writes[0].addr, writes[0].width, writes[0].value), (0x4a30_0104, 1, 1));
+    }
+}
@@ -0,0 +1,271 @@
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::fs::symlink;
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::sysroot::SysRoot;
+
+const LIB_FIRMWARE: &str = "/lib/firmware";
+
+#[derive(Debug, Error)]
+pub enum FirmwareStoreError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("invalid firmware name '{0}'")]
+    InvalidName(String),
+    #[error("no previous firmware for '{0}'")]
+    NoPrevious(String),
+}
+
+/// Installs firmware images into the kernel firmware search path.
+///
+/// Each image is written as `<name>-<hash>` where `<hash>` is a 64-bit
+/// FNV-1a digest of the contents, so identical builds land on the same file
+/// and `firmware` always names a specific build. Two symlinks track the
+/// deployment: `<name>` points at the current image and `<name>.prev` at the
+/// one before it, which `rollback` restores. Older images are removed, but
+/// only the store's own `<name>-<hash>` files; an image `<name>` pointed to
+/// before the first install (e.g. a distro firmware) is kept. A regular file
+/// at `<name>` is moved aside to `<name>.orig` and becomes the previous image.
+#[derive(Debug, Clone)]
+pub struct FirmwareStore {
+    dir: PathBuf,
+}
+
+impl FirmwareStore {
+    /// Use `dir` as the firmware directory.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        FirmwareStore { dir: dir.as_ref().to_path_buf() }
+    }
+
+    /// The kernel's default search path, `/lib/firmware`.
+    pub fn system() -> Self {
+        Self::system_in(&SysRoot::host())
+    }
+
+    /// `/lib/firmware` under `root` (see `SysRoot`).
+    pub fn system_in(root: &SysRoot) -> Self {
+        FirmwareStore::new(root.resolve(LIB_FIRMWARE))
+    }
+
+    /// Return the firmware directory.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Install `bytes` as the current image for `name`. Returns the file
+    /// name (relative to the store directory) to hand to `set_firmware`.
+    pub fn install_bytes(&self, name: &str, bytes: &[u8]) -> Result<String> {
+        check_name(name)?;
+        let file_name = format!("{}-{:016x}", name, fnv1a64(bytes));
+        let dest = self.dir.join(&file_name);
+        if !dest.exists() {
+            let tmp = self.dir.join(format!(".{}.tmp-{}", file_name, std::process::id()));
+            let res = (|| {
+                let mut f = fs::File::create(&tmp)?;
+                f.write_all(bytes)?;
+                f.sync_all()?;
+                fs::rename(&tmp, &dest)
+            })();
+            if let Err(e) = res {
+                let _ = fs::remove_file(&tmp);
+                return Err(e.into());
+            }
+        }
+
+        let current = match fs::symlink_metadata(self.dir.join(name)) {
+            Ok(meta) if !meta.file_type().is_symlink() => {
+                let orig = format!("{}.orig", name);
+                fs::rename(self.dir.join(name), self.dir.join(&orig))?;
+                Some(orig)
+            }
+            _ => self.current(name)?,
+        };
+        if current.as_deref() != Some(file_name.as_str()) {
+            let old_prev = self.previous(name)?;
+            if let Some(cur) = &current {
+                self.set_link(&format!("{}.prev", name), cur)?;
+            }
+            self.set_link(name, &file_name)?;
+            if let Some(old) = old_prev {
+                if old != file_name && Some(&old) != current.as_ref() && is_store_image(name, &old) {
+                    remove_if_exists(&self.dir.join(old))?;
+                }
+            }
+        }
+        Ok(file_name)
+    }
+
+    /// Install the contents of the file at `path` as the current image for `name`.
+    pub fn install_file<P: AsRef<Path>>(&self, name: &str, path: P) -> Result<String> {
+        let bytes = fs::read(path)?;
+        self.install_bytes(name, &bytes)
+    }
+
+    /// Target of the `name` link, i.e. the current image, if any. A regular
+    /// file at `name` is its own image and reported as `name`.
+    pub fn current(&self, name: &str) -> Result<Option<String>> {
+        self.read_link(name)
+    }
+
+    /// Target of the `<name>.prev` link, i.e. the previous image, if any.
+    pub fn previous(&self, name: &str) -> Result<Option<String>> {
+        self.read_link(&format!("{}.prev", name))
+    }
+
+    /// Make the previous image current again (and the current one previous).
+    /// Returns the file name now current.
+    pub fn rollback(&self, name: &str) -> Result<String> {
+        check_name(name)?;
+        let prev = self
+            .previous(name)?
+            .ok_or_else(|| FirmwareStoreError::NoPrevious(name.to_string()))?;
+        if let Some(cur) = self.current(name)? {
+            self.set_link(&format!("{}.prev", name), &cur)?;
+        }
+        self.set_link(name, &prev)?;
+        Ok(prev)
+    }
+
+    fn read_link(&self, link: &str) -> Result<Option<String>> {
+        let path = self.dir.join(link);
+        match fs::symlink_metadata(&path) {
+            Ok(meta) if !meta.file_type().is_symlink() => return Ok(Some(link.to_string())),
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+        let target = fs::read_link(&path)?;
+        let target = target.into_os_string().into_string().map_err(|t| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("non-UTF-8 link target {:?}", t))
+        })?;
+        Ok(Some(target))
+    }
+
+    /// Atomically point `link` at `target` by renaming a fresh symlink over it.
+    fn set_link(&self, link: &str, target: &str) -> Result<()> {
+        let tmp = self.dir.join(format!(".{}.lnk-{}", link, std::process::id()));
+        remove_if_exists(&tmp)?;
+        symlink(target, &tmp)?;
+        fs::rename(&tmp, self.dir.join(link))?;
+        Ok(())
+    }
+}
+
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name.starts_with('.') || name.contains('/') {
+        return Err(FirmwareStoreError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Whether `target` is an image this store wrote for `name`, `<name>-<16 hex digits>`.
+fn is_store_image(name: &str, target: &str) -> bool {
+    target
+        .strip_prefix(name)
+        .and_then(|rest| rest.strip_prefix('-'))
+        .is_some_and(|hash| hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+fn fnv1a64(data: &[u8]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in data {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h
+}
+
+pub type Result<T> = std::result::Result<T, FirmwareStoreError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_keeps_previous_and_rolls_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FirmwareStore::new(dir.path());
+
+        let a = store.install_bytes("pru0-fw", b"build a").unwrap();
+        assert_eq!(store.current("pru0-fw").unwrap().as_deref(), Some(a.as_str()));
+        assert_eq!(store.previous("pru0-fw").unwrap(), None);
+        assert_eq!(fs::read(dir.path().join("pru0-fw")).unwrap(), b"build a");
+
+        let b = store.install_bytes("pru0-fw", b"build b").unwrap();
+        assert_ne!(a, b);
+        assert_eq!(store.previous("pru0-fw").unwrap().as_deref(), Some(a.as_str()));
+
+        // reinstalling the current build changes nothing
+        assert_eq!(store.install_bytes("pru0-fw", b"build b").unwrap(), b);
+        assert_eq!(store.previous("pru0-fw").unwrap().as_deref(), Some(a.as_str()));
+
+        // a third build evicts the oldest image
+        let c = store.install_bytes("pru0-fw", b"build c").unwrap();
+        assert!(!dir.path().join(&a).exists());
+        assert_eq!(store.previous("pru0-fw").unwrap().as_deref(), Some(b.as_str()));
+
+        assert_eq!(store.rollback("pru0-fw").unwrap(), b);
+        assert_eq!(store.current("pru0-fw").unwrap().as_deref(), Some(b.as_str()));
+        assert_eq!(store.previous("pru0-fw").unwrap().as_deref(), Some(c.as_str()));
+    }
+
+    #[test]
+    fn keeps_image_linked_before_first_install() {
+        let dir = tempfile::tempdir().unwrap();
+        let distro = dir.path().join("am335x-pru0-rpmsg-echo.out");
+        fs::write(&distro, b"distro").unwrap();
+        symlink("am335x-pru0-rpmsg-echo.out", dir.path().join("am335x-pru0-fw")).unwrap();
+        let elsewhere = tempfile::tempdir().unwrap();
+        fs::write(elsewhere.path().join("pru1.out"), b"other").unwrap();
+        symlink(elsewhere.path().join("pru1.out"), dir.path().join("am335x-pru1-fw")).unwrap();
+        let store = FirmwareStore::new(dir.path());
+
+        store.install_bytes("am335x-pru0-fw", b"build a").unwrap();
+        assert_eq!(store.previous("am335x-pru0-fw").unwrap().as_deref(), Some("am335x-pru0-rpmsg-echo.out"));
+        store.install_bytes("am335x-pru0-fw", b"build b").unwrap();
+        store.install_bytes("am335x-pru0-fw", b"build c").unwrap();
+        assert_eq!(fs::read(&distro).unwrap(), b"distro");
+
+        // absolute targets are kept as they are
+        store.install_bytes("am335x-pru1-fw", b"build a").unwrap();
+        let prev = store.previous("am335x-pru1-fw").unwrap().unwrap();
+        assert_eq!(Path::new(&prev), elsewhere.path().join("pru1.out"));
+        assert_eq!(fs::read(dir.path().join("am335x-pru1-fw.prev")).unwrap(), b"other");
+    }
+
+    #[test]
+    fn moves_regular_file_aside() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("pru0-fw"), b"plain").unwrap();
+        let store = FirmwareStore::new(dir.path());
+        assert_eq!(store.current("pru0-fw").unwrap().as_deref(), Some("pru0-fw"));
+
+        let a = store.install_bytes("pru0-fw", b"build a").unwrap();
+        assert_eq!(store.current("pru0-fw").unwrap(), Some(a));
+        assert_eq!(store.previous("pru0-fw").unwrap().as_deref(), Some("pru0-fw.orig"));
+        store.install_bytes("pru0-fw", b"build b").unwrap();
+        assert_eq!(fs::read(dir.path().join("pru0-fw.orig")).unwrap(), b"plain");
+
+        store.rollback("pru0-fw").unwrap();
+        store.rollback("pru0-fw").unwrap();
+        assert_eq!(fs::read(dir.path().join("pru0-fw")).unwrap(), b"build b");
+    }
+
+    #[test]
+    fn rejects_bad_names() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = FirmwareStore::new(dir.path());
+        assert!(matches!(store.install_bytes("../x", b""), Err(FirmwareStoreError::InvalidName(_))));
+        assert!(matches!(store.rollback("fw"), Err(FirmwareStoreError::NoPrevious(_))));
+    }
+}
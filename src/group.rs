@@ -0,0 +1,273 @@
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::remoteproc::{RemoteProc, RemoteProcError, RemoteProcState};
+
+#[derive(Debug, Error)]
+pub enum CoreGroupError {
+    #[error("remoteproc: {0}")]
+    RemoteProc(#[from] RemoteProcError),
+    #[error("unknown core '{0}'")]
+    UnknownCore(String),
+    #[error("dependency cycle involving '{0}'")]
+    Cycle(String),
+    #[error("core '{core}' failed to start, group rolled back: {source}")]
+    StartFailed {
+        core: String,
+        #[source]
+        source: RemoteProcError,
+    },
+}
+
+struct Member {
+    key: String,
+    rproc: RemoteProc,
+    firmware: Option<String>,
+}
+
+/// Several remoteprocs started and stopped as one unit.
+///
+/// `start` stops every member, assigns firmwares, then starts the members in
+/// dependency order, waiting for each to be `Online` before starting the
+/// next. If any step fails the whole group is stopped, the previous
+/// firmware names are restored and the members that were running before
+/// are started again (best effort).
+pub struct CoreGroup {
+    members: Vec<Member>,
+    /// `(key, dependency)` pairs, checked against `members` in `start_order`.
+    after: Vec<(String, String)>,
+    timeout: Duration,
+}
+
+impl CoreGroup {
+    pub fn new() -> Self {
+        CoreGroup { members: Vec::new(), after: Vec::new(), timeout: Duration::from_secs(5) }
+    }
+
+    /// Add a core under `key`, optionally with the firmware to load.
+    pub fn add(mut self, key: &str, rproc: RemoteProc, firmware: Option<&str>) -> Self {
+        self.members.push(Member {
+            key: key.to_string(),
+            rproc,
+            firmware: firmware.map(str::to_string),
+        });
+        self
+    }
+
+    /// Start `key` only after `dependency` is `Online`. Both may be added
+    /// before or after this call; `start_order` fails with `UnknownCore` if
+    /// either is missing by then.
+    pub fn after(mut self, key: &str, dependency: &str) -> Self {
+        self.after.push((key.to_string(), dependency.to_string()));
+        self
+    }
+
+    /// How long each core may take to change state (default 5 s).
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Return the member registered under `key`.
+    pub fn get(&self, key: &str) -> Option<&RemoteProc> {
+        self.members.iter().find(|m| m.key == key).map(|m| &m.rproc)
+    }
+
+    /// Keys in start order: dependencies first, otherwise insertion order.
+    pub fn start_order(&self) -> Result<Vec<&str>> {
+        for (key, dep) in &self.after {
+            if let Some(unknown) = [key, dep].into_iter().find(|k| self.get(k).is_none()) {
+                return Err(CoreGroupError::UnknownCore(unknown.clone()));
+            }
+        }
+        let mut order: Vec<&str> = Vec::with_capacity(self.members.len());
+        while order.len() < self.members.len() {
+            let next = self.members.iter().find(|m| {
+                !order.contains(&m.key.as_str())
+                    && self.after.iter().all(|(k, d)| *k != m.key || order.contains(&d.as_str()))
+            });
+            match next {
+                Some(m) => order.push(&m.key),
+                None => {
+                    let stuck = self.members.iter().find(|m| !order.contains(&m.key.as_str())).unwrap();
+                    return Err(CoreGroupError::Cycle(stuck.key.clone()));
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    /// Stop all members, load their firmwares and start them in order.
+    pub fn start(&self) -> Result<()> {
+        let order = self.start_order()?;
+        let saved: Vec<String> = self
+            .members
+            .iter()
+            .map(|m| m.rproc.firmware())
+            .collect::<std::result::Result<_, _>>()?;
+        let was_running: Vec<bool> = self
+            .members
+            .iter()
+            .map(|m| m.rproc.state().map(|s| s == RemoteProcState::Online))
+            .collect::<std::result::Result<_, _>>()?;
+
+        let res = (|| {
+            for key in order.iter().rev() {
+                let m = self.member(key);
+                m.rproc.stop_and_wait(self.timeout).map_err(|e| fail(key, e))?;
+            }
+            for m in &self.members {
+                if let Some(fw) = &m.firmware {
+                    m.rproc.set_firmware(fw).map_err(|e| fail(&m.key, e))?;
+                }
+            }
+            for key in &order {
+                self.member(key).rproc.start_and_wait(self.timeout).map_err(|e| fail(key, e))?;
+            }
+            Ok(())
+        })();
+
+        if res.is_err() {
+            self.rollback(&order, &saved, &was_running);
+        }
+        res
+    }
+
+    /// Stop all members in reverse start order.
+    pub fn stop(&self) -> Result<()> {
+        for key in self.start_order()?.iter().rev() {
+            self.member(key).rproc.stop_and_wait(self.timeout)?;
+        }
+        Ok(())
+    }
+
+    fn rollback(&self, order: &[&str], saved: &[String], was_running: &[bool]) {
+        for key in order.iter().rev() {
+            let rp = &self.member(key).rproc;
+            if rp.state().map(|s| s != RemoteProcState::Offline).unwrap_or(true) {
+                let _ = rp.stop_and_wait(self.timeout);
+            }
+        }
+        for (m, fw) in self.members.iter().zip(saved) {
+            let _ = m.rproc.set_firmware(fw);
+        }
+        for key in order {
+            let i = self.members.iter().position(|m| m.key == *key).expect("key from start_order");
+            if was_running[i] {
+                let _ = self.members[i].rproc.start_and_wait(self.timeout);
+            }
+        }
+    }
+
+    fn member(&self, key: &str) -> &Member {
+        self.members.iter().find(|m| m.key == key).expect("key from start_order")
+    }
+}
+
+impl Default for CoreGroup {
+    fn default() -> Self {
+        CoreGroup::new()
+    }
+}
+
+fn fail(key: &str, source: RemoteProcError) -> CoreGroupError {
+    CoreGroupError::StartFailed { core: key.to_string(), source }
+}
+
+pub type Result<T> = std::result::Result<T, CoreGroupError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remoteproc::tests::{fake_tree, FakeKernel};
+    use crate::sysroot::SysRoot;
+    use std::fs;
+
+    fn two_cores() -> (tempfile::TempDir, SysRoot) {
+        let (dir, root) = fake_tree();
+        let rp2 = dir.path().join("sys/class/remoteproc/remoteproc2");
+        fs::create_dir_all(&rp2).unwrap();
+        fs::write(rp2.join("state"), "running\n").unwrap();
+        fs::write(rp2.join("firmware"), "old-pru1-fw\n").unwrap();
+        (dir, root)
+    }
+
+    fn group(root: &SysRoot) -> CoreGroup {
+        CoreGroup::new()
+            .add("pru1", RemoteProc::open_in(root, "remoteproc2").unwrap(), Some("pru1-fw"))
+            .add("pru0", RemoteProc::open_in(root, "remoteproc1").unwrap(), Some("pru0-fw"))
+            .after("pru1", "pru0")
+            .timeout(Duration::from_millis(200))
+    }
+
+    #[test]
+    fn orders_by_dependency() {
+        let (_dir, root) = two_cores();
+        let g = group(&root);
+        assert_eq!(g.start_order().unwrap(), vec!["pru0", "pru1"]);
+        let g = g.after("pru0", "pru1");
+        assert!(matches!(g.start_order(), Err(CoreGroupError::Cycle(_))));
+        let g = group(&root).after("pru0", "pru9");
+        assert!(matches!(g.start_order(), Err(CoreGroupError::UnknownCore(k)) if k == "pru9"));
+        let g = group(&root).after("rtu0", "pru0");
+        assert!(matches!(g.start_order(), Err(CoreGroupError::UnknownCore(k)) if k == "rtu0"));
+
+        // dependencies may be declared before the cores are added
+        let g = CoreGroup::new()
+            .after("pru1", "pru0")
+            .add("pru1", RemoteProc::open_in(&root, "remoteproc2").unwrap(), None)
+            .add("pru0", RemoteProc::open_in(&root, "remoteproc1").unwrap(), None);
+        assert_eq!(g.start_order().unwrap(), vec!["pru0", "pru1"]);
+    }
+
+    #[test]
+    fn starts_group_in_order() {
+        let (dir, root) = two_cores();
+        let base = dir.path().join("sys/class/remoteproc");
+        let _kernel = FakeKernel::spawn(vec![base.join("remoteproc1/state"), base.join("remoteproc2/state")]);
+        let g = group(&root);
+        g.start().unwrap();
+        for key in ["pru0", "pru1"] {
+            let rp = g.get(key).unwrap();
+            assert_eq!(rp.state().unwrap(), RemoteProcState::Online);
+            assert_eq!(rp.firmware().unwrap(), format!("{}-fw", key));
+        }
+        g.stop().unwrap();
+        assert_eq!(g.get("pru0").unwrap().state().unwrap(), RemoteProcState::Offline);
+    }
+
+    #[test]
+    fn rolls_back_on_failure() {
+        let (dir, root) = two_cores();
+        let base = dir.path().join("sys/class/remoteproc");
+        // only pru0 responds; pru1 never leaves the state we write
+        let _kernel = FakeKernel::spawn(vec![base.join("remoteproc1/state")]);
+        fs::write(base.join("remoteproc2/state"), "offline\n").unwrap();
+        let g = group(&root);
+        match g.start() {
+            Err(CoreGroupError::StartFailed { core, .. }) => assert_eq!(core, "pru1"),
+            other => panic!("expected StartFailed, got {:?}", other),
+        }
+        let pru0 = g.get("pru0").unwrap();
+        assert_eq!(pru0.state().unwrap(), RemoteProcState::Offline);
+        assert_eq!(pru0.firmware().unwrap(), "am335x-pru0-fw");
+        assert_eq!(g.get("pru1").unwrap().firmware().unwrap(), "old-pru1-fw");
+    }
+
+    #[test]
+    fn rollback_restarts_previously_running_cores() {
+        let (dir, root) = two_cores();
+        let base = dir.path().join("sys/class/remoteproc");
+        fs::write(base.join("remoteproc1/state"), "running\n").unwrap();
+        fs::write(base.join("remoteproc2/state"), "offline\n").unwrap();
+        // pru1 never starts, so the group is rolled back
+        let _kernel = FakeKernel::spawn(vec![base.join("remoteproc1/state")]);
+        let g = group(&root);
+        assert!(matches!(g.start(), Err(CoreGroupError::StartFailed { .. })));
+
+        let pru0 = g.get("pru0").unwrap();
+        assert_eq!(pru0.state().unwrap(), RemoteProcState::Online);
+        assert_eq!(pru0.firmware().unwrap(), "am335x-pru0-fw");
+    }
+}
@@ -0,0 +1,178 @@
+use std::ops::Deref;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::remoteproc::{RemoteProc, RemoteProcState, Result};
+
+/// How long `RunningProc` waits for each state change when dropped.
+const DROP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A started remoteproc that is stopped again when the guard is dropped,
+/// including during a panic unwind.
+///
+/// Created by `RemoteProc::start_guarded` and
+/// `RemoteProc::start_guarded_with`. The latter also puts back the firmware
+/// name that was configured before and restarts the core if it was running
+/// then, so the core is left as it was found. A core that is still booting
+/// is stopped once it settles, and a crashed one is recovered and then
+/// stopped. Errors while stopping or restoring on drop are ignored.
+pub struct RunningProc<'a> {
+    rproc: &'a RemoteProc,
+    restore_firmware: Option<String>,
+    restart: bool,
+    armed: bool,
+}
+
+impl<'a> RunningProc<'a> {
+    pub(crate) fn new(rproc: &'a RemoteProc, restore_firmware: Option<String>, restart: bool) -> Self {
+        RunningProc { rproc, restore_firmware, restart, armed: true }
+    }
+
+    /// A guard for a core that was running before, which it leaves running.
+    pub(crate) fn already_running(rproc: &'a RemoteProc) -> Self {
+        RunningProc { rproc, restore_firmware: None, restart: false, armed: false }
+    }
+
+    /// Whether the core is stopped on drop.
+    pub fn stops(&self) -> bool {
+        self.armed
+    }
+
+    /// The firmware name restored on drop, if any.
+    pub fn restores_firmware(&self) -> Option<&str> {
+        self.restore_firmware.as_deref()
+    }
+
+    /// Whether the core is started again on drop, after restoring its firmware.
+    pub fn restarts(&self) -> bool {
+        self.restart
+    }
+
+    /// Leave the core running and its firmware unchanged when the guard goes away.
+    pub fn detach(mut self) -> &'a RemoteProc {
+        self.armed = false;
+        self.rproc
+    }
+}
+
+impl Deref for RunningProc<'_> {
+    type Target = RemoteProc;
+
+    fn deref(&self) -> &RemoteProc {
+        self.rproc
+    }
+}
+
+impl Drop for RunningProc<'_> {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let _ = shut_down(self.rproc);
+        if let Some(fw) = &self.restore_firmware {
+            let _ = self.rproc.set_firmware(fw);
+        }
+        if self.restart {
+            let _ = self.rproc.start_and_wait(DROP_TIMEOUT);
+        }
+    }
+}
+
+/// Stop `rproc` from whatever state it is in: a booting core is waited for,
+/// a crashed one recovered first, as the kernel refuses `stop` in both.
+fn shut_down(rproc: &RemoteProc) -> Result<()> {
+    let deadline = Instant::now() + DROP_TIMEOUT;
+    let mut state = rproc.state()?;
+    while state == RemoteProcState::Booting && Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+        state = rproc.state()?;
+    }
+    if state == RemoteProcState::Crashed {
+        rproc.recover()?;
+        rproc.wait_for_state(RemoteProcState::Online, DROP_TIMEOUT)?;
+    }
+    rproc.stop_and_wait(DROP_TIMEOUT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::remoteproc::tests::{fake_tree, FakeKernel};
+    use crate::remoteproc::{RemoteProc, RemoteProcState};
+    use std::time::Duration;
+
+    #[test]
+    fn stops_and_restores_on_drop() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+        {
+            let running = rp.start_guarded_with("test-fw").unwrap();
+            assert_eq!(running.restores_firmware(), Some("am335x-pru0-fw"));
+            assert_eq!(running.firmware().unwrap(), "test-fw");
+            running.wait_for_state(RemoteProcState::Online, std::time::Duration::from_secs(1)).unwrap();
+        }
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Offline);
+        assert_eq!(rp.firmware().unwrap(), "am335x-pru0-fw");
+    }
+
+    #[test]
+    fn restarts_core_that_was_running() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        std::fs::write(rp.path().join("state"), "running\n").unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+        {
+            let running = rp.start_guarded_with("test-fw").unwrap();
+            assert!(running.restarts());
+            running.wait_for_state(RemoteProcState::Online, std::time::Duration::from_secs(1)).unwrap();
+        }
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Online);
+        assert_eq!(rp.firmware().unwrap(), "am335x-pru0-fw");
+    }
+
+    #[test]
+    fn stops_crashed_and_booting_cores() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        let state = rp.path().join("state");
+        std::fs::write(rp.path().join("recovery"), "disabled\n").unwrap();
+        let _kernel = FakeKernel::spawn(vec![state.clone()]);
+
+        let running = rp.start_guarded().unwrap();
+        running.wait_for_state(RemoteProcState::Online, Duration::from_secs(1)).unwrap();
+        std::fs::write(&state, "crashed\n").unwrap();
+        running.wait_for_state(RemoteProcState::Crashed, Duration::from_secs(1)).unwrap();
+        drop(running);
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Offline);
+
+        let running = rp.start_guarded().unwrap();
+        running.wait_for_state(RemoteProcState::Online, Duration::from_secs(1)).unwrap();
+        // the fake kernel moves a booting core on to running shortly after
+        std::fs::write(&state, "booting\n").unwrap();
+        drop(running);
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Offline);
+    }
+
+    #[test]
+    fn leaves_running_core_running() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        std::fs::write(rp.path().join("state"), "running\n").unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+        let running = rp.start_guarded().unwrap();
+        assert!(!running.stops());
+        drop(running);
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Online);
+    }
+
+    #[test]
+    fn detach_leaves_core_running() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+        let running = rp.start_guarded().unwrap();
+        running.wait_for_state(RemoteProcState::Online, std::time::Duration::from_secs(1)).unwrap();
+        running.detach();
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Online);
+    }
+}
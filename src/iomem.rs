@@ -0,0 +1,154 @@
+//! `/proc/iomem` parsing, used to check a physical range before mapping it.
+
+use std::fs;
+
+use crate::mmio::{MmioError, Result};
+use crate::sysroot::SysRoot;
+
+pub(crate) const PROC_IOMEM: &str = "/proc/iomem";
+
+/// One line of `/proc/iomem`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Resource {
+    pub start: u64,
+    /// Last byte of the resource (inclusive, as printed by the kernel).
+    pub end: u64,
+    pub name: String,
+    /// Nesting level (two spaces of indentation per level).
+    pub depth: usize,
+}
+
+impl Resource {
+    fn contains(&self, start: u64, end: u64) -> bool {
+        self.start <= start && end <= self.end
+    }
+
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.start <= end && start <= self.end
+    }
+
+    /// Whether the resource was claimed by a PRUSS / ICSSG driver. Only the
+    /// device name is looked at, i.e. the first word of e.g. "4a334000.pru iram".
+    pub fn is_pruss(&self) -> bool {
+        let device = self.name.split_whitespace().next().unwrap_or("");
+        device.contains("pruss") || device.contains("icssg") || [".pru", ".rtu", ".txpru"].iter().any(|s| device.ends_with(s))
+    }
+}
+
+/// Parse the text of `/proc/iomem`. Lines that do not parse are skipped.
+pub fn parse(text: &str) -> Vec<Resource> {
+    text.lines()
+        .filter_map(|line| {
+            let body = line.trim_start_matches(' ');
+            let depth = (line.len() - body.len()) / 2;
+            let (range, name) = body.split_once(" : ")?;
+            let (start, end) = range.split_once('-')?;
+            Some(Resource {
+                start: u64::from_str_radix(start, 16).ok()?,
+                end: u64::from_str_radix(end, 16).ok()?,
+                name: name.to_string(),
+                depth,
+            })
+        })
+        .collect()
+}
+
+/// Read and parse `/proc/iomem` under `root`.
+pub fn read_in(root: &SysRoot) -> Result<Vec<Resource>> {
+    Ok(parse(&fs::read_to_string(root.resolve(PROC_IOMEM))?))
+}
+
+/// Check that `len` bytes at `base` lie within one top-level resource and
+/// that the innermost resource containing them, or one of its parents,
+/// belongs to a PRUSS. Returns that innermost resource.
+pub fn check_pruss(resources: &[Resource], base: u64, len: usize) -> Result<&Resource> {
+    if !resources.is_empty() && resources.iter().all(|r| r.start == 0 && r.end == 0) {
+        return Err(MmioError::IomemHidden);
+    }
+    let end = base
+        .checked_add((len as u64).max(1) - 1)
+        .ok_or(MmioError::OutOfRange { addr: base, base, len })?;
+
+    let mut chain: Vec<&Resource> = Vec::new();
+    let mut depth = 0;
+    for r in resources {
+        // only look at children of the last resource on the chain
+        if r.depth != depth || (depth > 0 && !chain.last().is_some_and(|p| p.contains(r.start, r.end))) {
+            continue;
+        }
+        if r.contains(base, end) {
+            chain.push(r);
+            depth += 1;
+        } else if depth == 0 && r.overlaps(base, end) {
+            let names = resources
+                .iter()
+                .filter(|o| o.depth == 0 && o.overlaps(base, end))
+                .map(|o| o.name.clone())
+                .collect();
+            return Err(MmioError::CrossesResources { base, len, resources: names });
+        }
+    }
+
+    let Some(owner) = chain.last() else {
+        return Err(MmioError::Unclaimed { base, len });
+    };
+    if !chain.iter().any(|r| r.is_pruss()) {
+        return Err(MmioError::NotPruss { base, len, owner: owner.name.clone() });
+    }
+    Ok(owner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BBB: &str = "\
+44e10000-44e10fff : 44e10000.scm
+4a300000-4a37ffff : 4a300000.pruss
+  4a300000-4a301fff : dram0
+  4a302000-4a303fff : dram1
+  4a310000-4a312fff : shrdram2
+4a380000-4a38ffff : 4a380000.other
+4a500000-4a503fff : 4a500000.dsp
+  4a500000-4a500fff : 4a500000.dsp rproc.pru
+4b234000-4b2347ff : 4b234000.pru iram
+80000000-9fdfffff : System RAM
+  80008000-80cfffff : Kernel code
+";
+
+    #[test]
+    fn parses_nesting() {
+        let r = parse(BBB);
+        assert_eq!(r.len(), 11);
+        assert_eq!(r[2], Resource { start: 0x4a30_0000, end: 0x4a30_1fff, name: "dram0".into(), depth: 1 });
+    }
+
+    #[test]
+    fn accepts_pruss_ranges() {
+        let r = parse(BBB);
+        assert_eq!(check_pruss(&r, 0x4a30_2000, 0x2000).unwrap().name, "dram1");
+        assert_eq!(check_pruss(&r, 0x4a30_0000, 0x8_0000).unwrap().name, "4a300000.pruss");
+        // spans dram0 and dram1 but stays inside the pruss
+        assert_eq!(check_pruss(&r, 0x4a30_1000, 0x2000).unwrap().name, "4a300000.pruss");
+        // the device name is followed by the region name
+        assert_eq!(check_pruss(&r, 0x4b23_4000, 0x800).unwrap().name, "4b234000.pru iram");
+    }
+
+    #[test]
+    fn rejects_other_ranges() {
+        let r = parse(BBB);
+        assert!(matches!(check_pruss(&r, 0x4a40_0000, 4), Err(MmioError::Unclaimed { .. })));
+        assert!(matches!(check_pruss(&r, 0x8000_9000, 4), Err(MmioError::NotPruss { owner, .. }) if owner == "Kernel code"));
+        match check_pruss(&r, 0x4a37_f000, 0x2000) {
+            Err(MmioError::CrossesResources { resources, .. }) => {
+                assert_eq!(resources, vec!["4a300000.pruss", "4a380000.other"])
+            }
+            other => panic!("expected CrossesResources, got {:?}", other),
+        }
+        // ".pru" in the region name does not make a DSP a PRU
+        assert!(matches!(check_pruss(&r, 0x4a50_0000, 4), Err(MmioError::NotPruss { .. })));
+        assert!(matches!(check_pruss(&r, u64::MAX, 2), Err(MmioError::OutOfRange { .. })));
+        let hidden = parse("00000000-00000000 : 4a300000.pruss\n");
+        assert!(matches!(check_pruss(&hidden, 0x4a30_0000, 4), Err(MmioError::IomemHidden)));
+    }
+}
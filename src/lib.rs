@@ -1,10 +1,35 @@
 pub mod remoteproc;
+pub mod board;
+pub mod bus;
+pub mod coredump;
+pub mod cores;
+pub mod devicetree;
+pub mod firmware;
+pub mod firmware_store;
+pub mod group;
+pub mod guard;
+pub mod iomem;
+pub mod lock;
 pub mod mmio;
 pub mod rpmsg;
+pub mod supervisor;
+pub mod sysroot;
+pub mod trace;
 
-pub use remoteproc::{RemoteProc, RemoteProcError, RemoteProcState};
-pub use mmio::{Mmio, MmioError};
+pub use remoteproc::{
+    CoredumpMode, Recovery, RemoteProc, RemoteProcError, RemoteProcInfo, RemoteProcState, WriteRejection,
+};
+pub use mmio::{Mmio, MmioError, MmioRegion, MmioRo};
+pub use board::{Board, Region, SocProfile};
+pub use bus::{MemoryBus, MockBus};
+pub use devicetree::{DeviceTreeError, DtPruss};
 pub use rpmsg::{Rpmsg, RpmsgError};
+pub use sysroot::SysRoot;
+pub use cores::{CoreId, CoreKind};
+pub use guard::RunningProc;
+pub use lock::{CoreLock, LockError, LockMode};
+pub use firmware::{FirmwareError, PruFirmware};
+pub use firmware_store::{FirmwareStore, FirmwareStoreError};
 
 #[cfg(test)]
 mod tests {
@@ -0,0 +1,265 @@
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::sysroot::SysRoot;
+
+/// Directory holding the per-core lock files.
+pub(crate) const RUN_LOCK_DIR: &str = "/run/pru_rproc_user";
+
+const PROC_LOCKS: &str = "/proc/locks";
+
+/// Lock files are usable by every user, so unprivileged monitors can take
+/// a shared lock on a file root created.
+const LOCK_FILE_MODE: u32 = 0o666;
+
+const RETRY_MIN: Duration = Duration::from_millis(1);
+const RETRY_MAX: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error("io: {0}")]
+    Io(#[from] io::Error),
+    #[error("{} is locked{}", path.display(), holder.map(|p| format!(" by pid {}", p)).unwrap_or_default())]
+    Busy { path: PathBuf, holder: Option<u32> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Several holders at once (e.g. read-only monitors).
+    Shared,
+    /// A single holder; excludes shared holders too.
+    Exclusive,
+}
+
+/// An advisory `flock` on a per-core lock file, released on drop.
+///
+/// A busy lock names its holder as the kernel lists it in `/proc/locks`.
+/// This only protects against processes that also take the lock.
+#[derive(Debug)]
+pub struct CoreLock {
+    file: File,
+    path: PathBuf,
+    mode: LockMode,
+}
+
+impl CoreLock {
+    /// Lock `path` in `mode`. `timeout` of `None` blocks until the lock is
+    /// free; `Some(Duration::ZERO)` tries once.
+    pub fn acquire<P: AsRef<Path>>(path: P, mode: LockMode, timeout: Option<Duration>) -> Result<Self> {
+        Self::acquire_in(&SysRoot::host(), path, mode, timeout)
+    }
+
+    /// Same as `acquire`, reading `/proc/locks` under `root` (see `SysRoot`)
+    /// to name the holder of a busy lock. `path` is used as given.
+    pub fn acquire_in<P: AsRef<Path>>(
+        root: &SysRoot,
+        path: P,
+        mode: LockMode,
+        timeout: Option<Duration>,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let file = open_lock_file(&path, mode)?;
+        let op = match mode {
+            LockMode::Shared => libc::LOCK_SH,
+            LockMode::Exclusive => libc::LOCK_EX,
+        };
+
+        match timeout {
+            None => flock(&file, op)?,
+            Some(timeout) => {
+                // a timeout too large to represent never expires
+                let deadline = Instant::now().checked_add(timeout);
+                let mut delay = RETRY_MIN;
+                loop {
+                    match flock(&file, op | libc::LOCK_NB) {
+                        Ok(()) => break,
+                        Err(e) if e.raw_os_error() == Some(libc::EWOULDBLOCK) => {
+                            let now = Instant::now();
+                            if deadline.is_some_and(|d| now >= d) {
+                                // best effort: /proc may be missing or hide other users' PIDs
+                                let holder = holders(root, &file).ok().and_then(|h| h.first().copied());
+                                return Err(LockError::Busy { path, holder });
+                            }
+                            thread::sleep(deadline.map_or(delay, |d| delay.min(d - now)));
+                            delay = (delay * 2).min(RETRY_MAX);
+                        }
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+            }
+        }
+        Ok(CoreLock { file, path, mode })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn mode(&self) -> LockMode {
+        self.mode
+    }
+}
+
+impl Drop for CoreLock {
+    fn drop(&mut self) {
+        let _ = flock(&self.file, libc::LOCK_UN);
+    }
+}
+
+fn flock(file: &File, op: libc::c_int) -> io::Result<()> {
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), op) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Open (creating if needed) the lock file. `flock` needs no write access,
+/// so a shared lock opens it read-only; a new file gets `LOCK_FILE_MODE`
+/// regardless of the umask.
+fn open_lock_file(path: &Path, mode: LockMode) -> io::Result<File> {
+    match OpenOptions::new().write(true).create_new(true).mode(LOCK_FILE_MODE).open(path) {
+        Ok(file) => {
+            file.set_permissions(fs::Permissions::from_mode(LOCK_FILE_MODE))?;
+            if mode == LockMode::Exclusive {
+                return Ok(file);
+            }
+        }
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(e),
+    }
+    match mode {
+        LockMode::Shared => File::open(path),
+        LockMode::Exclusive => OpenOptions::new().read(true).write(true).open(path),
+    }
+}
+
+/// PIDs of the processes holding a `flock` on `file`, from `/proc/locks` under `root`.
+fn holders(root: &SysRoot, file: &File) -> io::Result<Vec<u32>> {
+    let meta = file.metadata()?;
+    Ok(parse_locks(&fs::read_to_string(root.resolve(PROC_LOCKS))?, meta.dev(), meta.ino()))
+}
+
+/// Holders of `flock`s on inode `ino` of device `dev` in `/proc/locks` text,
+/// e.g. `1: FLOCK  ADVISORY  WRITE 1234 b3:02:5678 0 EOF`. Waiters (`->`)
+/// and PIDs outside our namespace (shown as 0) are skipped.
+fn parse_locks(text: &str, dev: u64, ino: u64) -> Vec<u32> {
+    // glibc's encoding of major/minor in `st_dev`
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    text.lines()
+        .filter_map(|line| {
+            let f: Vec<&str> = line.split_whitespace().collect();
+            if f.get(1) != Some(&"FLOCK") {
+                return None;
+            }
+            let pid = f.get(4)?.parse::<u32>().ok().filter(|p| *p != 0)?;
+            let mut id = f.get(5)?.split(':');
+            let maj = u64::from_str_radix(id.next()?, 16).ok()?;
+            let min = u64::from_str_radix(id.next()?, 16).ok()?;
+            let i = id.next()?.parse::<u64>().ok()?;
+            (maj == major && min == minor && i == ino).then_some(pid)
+        })
+        .collect()
+}
+
+pub type Result<T> = std::result::Result<T, LockError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exclusive_excludes_and_reports_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("run/remoteproc1.lock");
+        let held = CoreLock::acquire(&path, LockMode::Exclusive, Some(Duration::ZERO)).unwrap();
+        match CoreLock::acquire(&path, LockMode::Shared, Some(Duration::from_millis(5))) {
+            Err(LockError::Busy { holder, .. }) => assert_eq!(holder, Some(std::process::id())),
+            other => panic!("expected Busy, got {:?}", other),
+        }
+        drop(held);
+        CoreLock::acquire(&path, LockMode::Exclusive, Some(Duration::ZERO)).unwrap();
+    }
+
+    #[test]
+    fn parses_proc_locks() {
+        let text = "\
+1: FLOCK  ADVISORY  WRITE 4321 b3:02:5678 0 EOF
+1: -> FLOCK  ADVISORY  WRITE 999 b3:02:5678 0 EOF
+2: FLOCK  ADVISORY  READ 77 b3:02:1111 0 EOF
+3: POSIX  ADVISORY  WRITE 88 b3:02:5678 0 EOF
+4: FLOCK  ADVISORY  READ 0 b3:02:5678 0 EOF
+";
+        assert_eq!(parse_locks(text, 0xb302, 5678), vec![4321]);
+        assert!(parse_locks(text, 0xb303, 5678).is_empty());
+    }
+
+    #[test]
+    fn released_lock_has_no_holder() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("remoteproc1.lock");
+        let a = CoreLock::acquire(&path, LockMode::Shared, None).unwrap();
+        let b = CoreLock::acquire(&path, LockMode::Shared, None).unwrap();
+        assert_eq!(holders(&SysRoot::host(), &a.file).unwrap(), vec![std::process::id(); 2]);
+        drop(b);
+        assert_eq!(holders(&SysRoot::host(), &a.file).unwrap(), vec![std::process::id()]);
+        drop(a);
+        assert!(holders(&SysRoot::host(), &File::open(&path).unwrap()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn creates_lock_file_for_everyone() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("remoteproc1.lock");
+        let lock = CoreLock::acquire(&path, LockMode::Shared, None).unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, LOCK_FILE_MODE);
+        drop(lock);
+        // an existing read-only file is enough for a shared lock
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o444)).unwrap();
+        CoreLock::acquire(&path, LockMode::Shared, Some(Duration::ZERO)).unwrap();
+    }
+
+    #[test]
+    fn holder_read_from_proc_locks_under_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = SysRoot::new(dir.path());
+        let path = dir.path().join("run/remoteproc1.lock");
+        let _held = CoreLock::acquire_in(&root, &path, LockMode::Exclusive, None).unwrap();
+        let meta = fs::metadata(&path).unwrap();
+        let (major, minor) = (libc::major(meta.dev()), libc::minor(meta.dev()));
+        fs::create_dir_all(dir.path().join("proc")).unwrap();
+        let line = format!("1: FLOCK  ADVISORY  WRITE 4242 {:02x}:{:02x}:{} 0 EOF\n", major, minor, meta.ino());
+        fs::write(dir.path().join("proc/locks"), line).unwrap();
+        match CoreLock::acquire_in(&root, &path, LockMode::Shared, Some(Duration::ZERO)) {
+            Err(LockError::Busy { holder, .. }) => assert_eq!(holder, Some(4242)),
+            other => panic!("expected Busy, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn shared_locks_coexist() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("remoteproc1.lock");
+        let _a = CoreLock::acquire(&path, LockMode::Shared, None).unwrap();
+        let _b = CoreLock::acquire(&path, LockMode::Shared, Some(Duration::ZERO)).unwrap();
+        assert!(matches!(
+            CoreLock::acquire(&path, LockMode::Exclusive, Some(Duration::ZERO)),
+            Err(LockError::Busy { .. })
+        ));
+    }
+}
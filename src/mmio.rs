@@ -1,82 +1,1026 @@
-use memmap2::{MmapMut, MmapOptions};
-use std::fs::OpenOptions;
+use memmap2::{Mmap, MmapMut, MmapOptions};
+use std::fs::{self, File, OpenOptions};
 use std::io;
-use std::path::Path;
+use std::mem::size_of;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use thiserror::Error;
 
+use crate::board::{Region, SocProfile, AM335X};
+use crate::bus::MemoryBus;
+use crate::iomem;
+use crate::sysroot::SysRoot;
+
+const SYS_UIO: &str = "/sys/class/uio";
+const DEV: &str = "/dev";
+const DEV_MEM: &str = "/dev/mem";
+
 #[derive(Debug, Error)]
 pub enum MmioError {
     #[error("io: {0}")]
     Io(#[from] io::Error),
     #[error("map error: {0}")]
     Map(String),
+    #[error("unexpected value '{value}' in {path}")]
+    BadAttr { path: PathBuf, value: String },
+    #[error("{soc} has no region {region} in PRUSS {icss}")]
+    UnknownRegion {
+        soc: &'static str,
+        icss: usize,
+        region: Region,
+    },
+    #[error("{soc} has no PRUSS {icss}")]
+    UnknownPruss { soc: &'static str, icss: usize },
+    #[error("{base:#x} (+{len:#x}) is not claimed by any device in /proc/iomem")]
+    Unclaimed { base: u64, len: usize },
+    #[error("{base:#x} (+{len:#x}) crosses resources {resources:?}")]
+    CrossesResources {
+        base: u64,
+        len: usize,
+        resources: Vec<String>,
+    },
+    #[error("{base:#x} (+{len:#x}) belongs to '{owner}', not a PRUSS")]
+    NotPruss { base: u64, len: usize, owner: String },
+    #[error("/proc/iomem addresses are hidden (needs root)")]
+    IomemHidden,
+    #[error("/dev/mem refused {base:#x}: blocked by CONFIG_STRICT_DEVMEM or missing CAP_SYS_RAWIO")]
+    StrictDevmem { base: u64 },
+    #[error("address {addr:#x} outside mapping {base:#x} (+{len:#x})")]
+    OutOfRange { addr: u64, base: u64, len: usize },
+    #[error("{len:#x} bytes at offset {offset:#x} run past the end of {path} ({size:#x} bytes)")]
+    PastEndOfFile { path: PathBuf, offset: u64, len: usize, size: u64 },
+    #[error("address {addr:#x} not aligned to {align} bytes")]
+    Misaligned { addr: u64, align: usize },
+    #[error("{len:#x} bytes at offset {offset:#x} overlap a region already split off")]
+    RegionInUse { offset: usize, len: usize },
+    #[error("write to {addr:#x} through a read-only mapping")]
+    ReadOnly { addr: u64 },
+    #[error("address {addr:#x} belongs to a split-off MmioRegion")]
+    Claimed { addr: u64 },
+    #[error("unsupported board (compatible: {0})")]
+    UnsupportedBoard(String),
 }
 
 pub struct Mmio {
-    map: MmapMut,
+    map: Arc<Mapping>,
+    base: u64,
+    /// Offset of `base` within the page-aligned mapping.
+    page_offset: usize,
+}
+
+/// A writable mapping shared by an `Mmio` and the `MmioRegion`s split from it.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+    /// `(id, offset, len)` of the regions currently split off.
+    claims: Mutex<Vec<(u64, usize, usize)>>,
+    /// `claims.len()`, so `Mmio` accesses skip the lock when nothing is split.
+    claimed: AtomicUsize,
+    /// Id of the next claim; ids are never reused.
+    next_claim: AtomicU64,
+    _map: MmapMut,
+}
+
+// SAFETY: the mapping is only accessed through volatile loads and stores via
+// `ptr`; `Mmio` needs `&mut` to write or split off a region and refuses
+// claimed ranges, and each `MmioRegion` owns a claimed range no other handle
+// touches.
+unsafe impl Send for Mapping {}
+unsafe impl Sync for Mapping {}
+
+impl Mapping {
+    fn new(mut map: MmapMut) -> Arc<Self> {
+        Arc::new(Mapping {
+            ptr: map.as_mut_ptr(),
+            len: map.len(),
+            claims: Mutex::new(Vec::new()),
+            claimed: AtomicUsize::new(0),
+            next_claim: AtomicU64::new(0),
+            _map: map,
+        })
+    }
+
+    fn is_claimed(&self, off: usize, len: usize) -> bool {
+        self.claimed.load(Ordering::Acquire) > 0
+            && self.claims.lock().unwrap().iter().any(|&(_, o, l)| off < o + l && o < off + len)
+    }
+}
+
+/// A disjoint part of an `Mmio` mapping, returned by `Mmio::split_region`.
+///
+/// Regions are `Send` and keep the mapping alive, so each can be moved to its
+/// own thread; the range is released again when the region is dropped.
+/// Addresses are physical, as for `Mmio`.
+pub struct MmioRegion {
+    map: Arc<Mapping>,
     base: u64,
+    /// Offset of `base` within the mapping.
+    start: usize,
+    len: usize,
+    /// Id of this region's entry in `Mapping::claims`.
+    claim: u64,
 }
 
-// Common AM335x / BeagleBone Black PRU/PRUSS addresses.
-// These are provided as convenience defaults; verify against your board/device tree.
+// AM335x / BeagleBone Black PRU-ICSS addresses, kept for the convenience
+// mappers below. Other SoCs: use `Board::profile` with `Mmio::map_region`.
 pub const PRUSS_BASE: u64 = 0x4A300000;
-pub const PRUSS_SIZE: usize = 0x0002_0000; // 128 KiB (typical PRUSS region size)
+pub const PRUSS_SIZE: usize = 0x0008_0000; // 512 KiB
 
-pub const PRU0_DRAM_BASE: u64 = 0x4A310000;
+pub const PRU0_DRAM_BASE: u64 = 0x4A300000;
 pub const PRU0_DRAM_SIZE: usize = 0x0000_2000; // 8 KiB
 
-pub const PRU1_DRAM_BASE: u64 = 0x4A320000;
+pub const PRU1_DRAM_BASE: u64 = 0x4A302000;
 pub const PRU1_DRAM_SIZE: usize = 0x0000_2000; // 8 KiB
 
 impl Mmio {
     /// Map `len` bytes starting at physical `base`. Requires root privileges.
     pub fn map(base: u64, len: usize) -> Result<Self> {
-        let dev = OpenOptions::new().read(true).write(true).open(Path::new("/dev/mem"))?;
-        let page_offset = (base as usize) & (4096 - 1);
-        let aligned_base = base - (page_offset as u64);
-        let aligned_len = len + page_offset;
+        Mmio::map_with(Path::new(DEV_MEM), base, len, |e| MmioError::Map(e.to_string()))
+    }
+
+    fn map_with(dev_mem: &Path, base: u64, len: usize, map_err: impl FnOnce(io::Error) -> MmioError) -> Result<Self> {
+        let dev = OpenOptions::new().read(true).write(true).open(dev_mem)?;
+        Mmio::map_file_range(&dev, base, len, base, map_err)
+    }
 
+    /// Map `len` bytes of `file` at `offset`, addressed as physical `base`.
+    fn map_file_range(
+        file: &File,
+        offset: u64,
+        len: usize,
+        base: u64,
+        map_err: impl FnOnce(io::Error) -> MmioError,
+    ) -> Result<Self> {
+        let page_offset = (offset % page_size()) as usize;
         let map = unsafe {
             MmapOptions::new()
-                .offset(aligned_base)
-                .len(aligned_len)
+                .offset(offset - page_offset as u64)
+                .len(len + page_offset)
+                .map_mut(file)
+                .map_err(map_err)?
+        };
+        Ok(Mmio { map: Mapping::new(map), base, page_offset })
+    }
+
+    /// Map `len` bytes of the file at `path` starting at `offset`. Addresses
+    /// passed to the accessors are file offsets; use `rebase` to address the
+    /// mapping by the physical addresses it stands in for.
+    pub fn map_file<P: AsRef<Path>>(path: P, offset: u64, len: usize) -> Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        check_file_len(&file, path.as_ref(), offset, len)?;
+        Mmio::map_file_range(&file, offset, len, offset, |e| MmioError::Map(e.to_string()))
+    }
+
+    /// Map memory region `index` of UIO device `uio` (e.g. `"uio0"`) as
+    /// described by `/sys/class/uio/<uio>/maps/map<index>`. Addresses are the
+    /// region's physical addresses. Only needs access to `/dev/<uio>`.
+    pub fn map_uio(uio: &str, index: usize) -> Result<Self> {
+        Mmio::map_uio_in(&SysRoot::host(), uio, index)
+    }
+
+    pub fn map_uio_in(root: &SysRoot, uio: &str, index: usize) -> Result<Self> {
+        let dir = root.resolve(SYS_UIO).join(uio).join("maps").join(format!("map{}", index));
+        let addr = read_hex_attr(&dir.join("addr"))?;
+        let size = read_hex_attr(&dir.join("size"))? as usize;
+        // older kernels have no `offset`: regions then start on a page
+        let page_offset = match read_hex_attr(&dir.join("offset")) {
+            Ok(v) => v as usize,
+            Err(MmioError::Io(e)) if e.kind() == io::ErrorKind::NotFound => 0,
+            Err(e) => return Err(e),
+        };
+        let dev = OpenOptions::new().read(true).write(true).open(root.resolve(DEV).join(uio))?;
+        // the UIO driver selects region N by an mmap offset of N pages
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(index as u64 * page_size())
+                .len(size + page_offset)
                 .map_mut(&dev)
                 .map_err(|e| MmioError::Map(e.to_string()))?
         };
+        Ok(Mmio { map: Mapping::new(map), base: addr, page_offset })
+    }
+
+    /// Address this mapping as starting at `base` instead. Accesses must then
+    /// be aligned both as addresses and within the underlying mapping.
+    pub fn rebase(mut self, base: u64) -> Self {
+        self.base = base;
+        self
+    }
+
+    /// Like `map`, but first check `/proc/iomem` that the range lies within a
+    /// PRUSS resource, and report an `EPERM` from `/dev/mem` as `StrictDevmem`.
+    pub fn map_checked(base: u64, len: usize) -> Result<Self> {
+        Mmio::map_checked_in(&SysRoot::host(), base, len)
+    }
+
+    /// Same as `map_checked`, reading `/proc/iomem` and opening `/dev/mem`
+    /// under `root` (see `SysRoot`).
+    pub fn map_checked_in(root: &SysRoot, base: u64, len: usize) -> Result<Self> {
+        let resources = iomem::read_in(root)?;
+        iomem::check_pruss(&resources, base, len)?;
+        let strict = |e: io::Error| match e.raw_os_error() {
+            Some(libc::EPERM) => MmioError::StrictDevmem { base },
+            _ => MmioError::Map(e.to_string()),
+        };
+        match Mmio::map_with(&root.resolve(DEV_MEM), base, len, strict) {
+            Err(MmioError::Io(e)) if e.raw_os_error() == Some(libc::EPERM) => Err(MmioError::StrictDevmem { base }),
+            other => other,
+        }
+    }
+
+    /// Map `region` of PRUSS instance `icss` as described by `profile`.
+    pub fn map_region(profile: &SocProfile, icss: usize, region: Region) -> Result<Self> {
+        let (base, len) = profile.region(icss, region).ok_or(MmioError::UnknownRegion {
+            soc: profile.name,
+            icss,
+            region,
+        })?;
+        Mmio::map(base, len)
+    }
 
-        Ok(Mmio { map, base })
+    /// Map the whole of PRUSS instance `icss` as described by `profile`.
+    pub fn map_pruss_of(profile: &SocProfile, icss: usize) -> Result<Self> {
+        let pruss = profile
+            .instances
+            .get(icss)
+            .ok_or(MmioError::UnknownPruss { soc: profile.name, icss })?;
+        Mmio::map(pruss.base, pruss.size)
     }
 
-    /// Convenience: map the whole PRUSS (ICSS) region using the common BBB address.
+    /// Convenience: map the whole AM335x (BeagleBone Black) PRU-ICSS.
     pub fn map_pruss() -> Result<Self> {
-        Mmio::map(PRUSS_BASE, PRUSS_SIZE)
+        Mmio::map_pruss_of(&AM335X, 0)
     }
 
-    /// Convenience: map PRU0 data RAM (verify address for your board).
+    /// Convenience: map AM335x PRU0 data RAM.
     pub fn map_pru0_dram() -> Result<Self> {
-        Mmio::map(PRU0_DRAM_BASE, PRU0_DRAM_SIZE)
+        Mmio::map_region(&AM335X, 0, Region::Dram(0))
     }
 
-    /// Convenience: map PRU1 data RAM (verify address for your board).
+    /// Convenience: map AM335x PRU1 data RAM.
     pub fn map_pru1_dram() -> Result<Self> {
-        Mmio::map(PRU1_DRAM_BASE, PRU1_DRAM_SIZE)
+        Mmio::map_region(&AM335X, 0, Region::Dram(1))
+    }
+
+    /// Physical address of the first mapped byte.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Number of mapped bytes starting at `base`.
+    pub fn len(&self) -> usize {
+        self.map.len - self.page_offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Hand out `len` bytes starting `offset` bytes past `base` as a separate
+    /// `MmioRegion`. Regions may not overlap each other, and while a region
+    /// exists this `Mmio` refuses accesses to it with `MmioError::Claimed`.
+    /// Takes `&mut self` so no access through this `Mmio` is in flight while
+    /// the range is claimed.
+    pub fn split_region(&mut self, offset: usize, len: usize) -> Result<MmioRegion> {
+        if offset.checked_add(len).is_none_or(|end| end > self.len()) {
+            let addr = self.base.saturating_add(offset as u64);
+            return Err(MmioError::OutOfRange { addr, base: self.base, len: self.len() });
+        }
+        let start = self.page_offset + offset;
+        let mut claims = self.map.claims.lock().unwrap();
+        if claims.iter().any(|&(_, o, l)| start < o + l && o < start + len) {
+            return Err(MmioError::RegionInUse { offset, len });
+        }
+        let claim = self.map.next_claim.fetch_add(1, Ordering::Relaxed);
+        claims.push((claim, start, len));
+        self.map.claimed.store(claims.len(), Ordering::Release);
+        Ok(MmioRegion { map: self.map.clone(), base: self.base + offset as u64, start, len, claim })
+    }
+
+    fn offset(&self, addr: u64, size: usize) -> Result<usize> {
+        let off = access_offset(self.base, self.len(), self.page_offset, addr, size)?;
+        if self.map.is_claimed(off, size) {
+            return Err(MmioError::Claimed { addr });
+        }
+        Ok(off)
+    }
+
+    /// Pointer to `n` bytes at `addr`, checking bounds and claims.
+    fn span(&self, addr: u64, n: usize) -> Result<*mut u8> {
+        let off = span_offset(self.base, self.len(), self.page_offset, addr, n)?;
+        if self.map.is_claimed(off, n) {
+            return Err(MmioError::Claimed { addr });
+        }
+        // SAFETY: `off + n` lies within the mapping.
+        Ok(unsafe { self.map.ptr.add(off) })
+    }
+
+    /// Single volatile load of a `T` at physical `addr`.
+    fn read_volatile<T: Copy>(&self, addr: u64) -> Result<T> {
+        let off = self.offset(addr, size_of::<T>())?;
+        // SAFETY: `offset` checked that the access is in bounds and aligned.
+        Ok(unsafe { ptr::read_volatile(self.map.ptr.add(off) as *const T) })
+    }
+
+    /// Single volatile store of a `T` at physical `addr`.
+    fn write_volatile<T: Copy>(&mut self, addr: u64, val: T) -> Result<()> {
+        let off = self.offset(addr, size_of::<T>())?;
+        // SAFETY: as for `read_volatile`.
+        unsafe { ptr::write_volatile(self.map.ptr.add(off) as *mut T, val) };
+        Ok(())
+    }
+}
+
+impl MmioRegion {
+    /// Physical address of the first byte of the region.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn offset(&self, addr: u64, size: usize) -> Result<usize> {
+        access_offset(self.base, self.len, self.start, addr, size)
+    }
+
+    /// Pointer to `n` bytes at `addr`, checking they lie in the region.
+    fn span(&self, addr: u64, n: usize) -> Result<*mut u8> {
+        let off = span_offset(self.base, self.len, self.start, addr, n)?;
+        // SAFETY: `off + n` lies within the region, hence the mapping.
+        Ok(unsafe { self.map.ptr.add(off) })
+    }
+
+    /// Single volatile load of a `T` at physical `addr`.
+    fn read_volatile<T: Copy>(&self, addr: u64) -> Result<T> {
+        let off = self.offset(addr, size_of::<T>())?;
+        // SAFETY: `offset` checked that the access lies within this region.
+        Ok(unsafe { ptr::read_volatile(self.map.ptr.add(off) as *const T) })
+    }
+
+    /// Single volatile store of a `T` at physical `addr`.
+    fn write_volatile<T: Copy>(&mut self, addr: u64, val: T) -> Result<()> {
+        let off = self.offset(addr, size_of::<T>())?;
+        // SAFETY: as for `read_volatile`.
+        unsafe { ptr::write_volatile(self.map.ptr.add(off) as *mut T, val) };
+        Ok(())
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        let mut claims = self.map.claims.lock().unwrap_or_else(|e| e.into_inner());
+        claims.retain(|&(id, _, _)| id != self.claim);
+        self.map.claimed.store(claims.len(), Ordering::Release);
+    }
+}
+
+/// A read-only mapping: the device is opened `O_RDONLY` and mapped
+/// `PROT_READ`, and only read accessors exist. Returned by `Mmio::map_readonly`.
+/// As a `MemoryBus` (e.g. for `SymbolView`) every write fails with
+/// `MmioError::ReadOnly`.
+pub struct MmioRo {
+    map: Mmap,
+    base: u64,
+    page_offset: usize,
+}
+
+impl Mmio {
+    /// Map `len` bytes at physical `base` for reading only. Requires root privileges.
+    pub fn map_readonly(base: u64, len: usize) -> Result<MmioRo> {
+        Mmio::map_file_readonly(DEV_MEM, base, len).map(|m| m.rebase(base))
+    }
+
+    /// Read-only variant of `map_file`.
+    pub fn map_file_readonly<P: AsRef<Path>>(path: P, offset: u64, len: usize) -> Result<MmioRo> {
+        let file = File::open(&path)?;
+        check_file_len(&file, path.as_ref(), offset, len)?;
+        let page_offset = (offset % page_size()) as usize;
+        let map = unsafe {
+            MmapOptions::new()
+                .offset(offset - page_offset as u64)
+                .len(len + page_offset)
+                .map(&file)
+                .map_err(|e| MmioError::Map(e.to_string()))?
+        };
+        Ok(MmioRo { map, base: offset, page_offset })
+    }
+}
+
+impl MmioRo {
+    /// Physical address of the first mapped byte.
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
+    /// Number of mapped bytes starting at `base`.
+    pub fn len(&self) -> usize {
+        self.map.len() - self.page_offset
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Address this mapping as starting at `base` instead (see `Mmio::rebase`).
+    pub fn rebase(mut self, base: u64) -> Self {
+        self.base = base;
+        self
+    }
+
+    fn offset(&self, addr: u64, size: usize) -> Result<usize> {
+        access_offset(self.base, self.len(), self.page_offset, addr, size)
     }
 
-    fn offset(&self, addr: u64) -> usize {
-        (addr - self.base) as usize
+    /// Pointer to `n` bytes at `addr`, checking bounds. Only ever read through.
+    fn span(&self, addr: u64, n: usize) -> Result<*mut u8> {
+        let off = span_offset(self.base, self.len(), self.page_offset, addr, n)?;
+        // SAFETY: `off + n` lies within the mapping.
+        Ok(unsafe { self.map.as_ptr().add(off) as *mut u8 })
     }
 
-    pub fn read_u32(&self, addr: u64) -> u32 {
-        let off = self.offset(addr);
-        let bytes = &self.map[off..off + 4];
-        u32::from_le_bytes(bytes.try_into().unwrap())
+    /// Single volatile load of a `T` at physical `addr`.
+    fn read_volatile<T: Copy>(&self, addr: u64) -> Result<T> {
+        let off = self.offset(addr, size_of::<T>())?;
+        // SAFETY: `offset` checked that the access is in bounds and aligned.
+        Ok(unsafe { ptr::read_volatile(self.map.as_ptr().add(off) as *const T) })
     }
+}
+
+/// Offset into a mapping of a `size`-byte access at `addr`, checking bounds
+/// and natural alignment. Alignment is checked on the offset as well, as it
+/// differs from that of `addr` after a `rebase` to a differently aligned base.
+fn access_offset(base: u64, len: usize, page_offset: usize, addr: u64, size: usize) -> Result<usize> {
+    let off = span_offset(base, len, page_offset, addr, size)?;
+    if !addr.is_multiple_of(size as u64) || !off.is_multiple_of(size) {
+        return Err(MmioError::Misaligned { addr, align: size });
+    }
+    Ok(off)
+}
+
+/// Offset into a mapping of `n` bytes at `addr`, checking bounds only.
+fn span_offset(base: u64, len: usize, page_offset: usize, addr: u64, n: usize) -> Result<usize> {
+    let rel = addr
+        .checked_sub(base)
+        .filter(|rel| rel.checked_add(n as u64).is_some_and(|end| end <= len as u64))
+        .ok_or(MmioError::OutOfRange { addr, base, len })?;
+    Ok(page_offset + rel as usize)
+}
+
+/// Fail if `len` bytes at `offset` run past the end of regular file `file`;
+/// touching a mapped page beyond it raises `SIGBUS`. Device files are not checked.
+fn check_file_len(file: &File, path: &Path, offset: u64, len: usize) -> Result<()> {
+    let meta = file.metadata()?;
+    if meta.is_file() && offset.checked_add(len as u64).is_none_or(|end| end > meta.len()) {
+        return Err(MmioError::PastEndOfFile { path: path.to_path_buf(), offset, len, size: meta.len() });
+    }
+    Ok(())
+}
+
+/// Copy device memory at `src` into `out`.
+///
+/// Device mappings on ARM fault on unaligned accesses and on the wide or
+/// multi-register loads `memcpy` may use, so this does single volatile byte
+/// accesses up to a 4-byte boundary, aligned 32-bit words, then bytes.
+///
+/// # Safety
+///
+/// `src` must be valid for reads of `out.len()` bytes.
+unsafe fn copy_from_device(src: *const u8, out: &mut [u8]) {
+    let head = ((4 - src as usize % 4) % 4).min(out.len());
+    let words = (out.len() - head) / 4;
+    for (i, b) in out[..head].iter_mut().enumerate() {
+        *b = ptr::read_volatile(src.add(i));
+    }
+    for w in 0..words {
+        let i = head + w * 4;
+        let word = ptr::read_volatile(src.add(i) as *const u32);
+        out[i..i + 4].copy_from_slice(&word.to_ne_bytes());
+    }
+    for (i, b) in out.iter_mut().enumerate().skip(head + words * 4) {
+        *b = ptr::read_volatile(src.add(i));
+    }
+}
 
-    pub fn write_u32(&mut self, addr: u64, val: u32) {
-        let off = self.offset(addr);
-        let bytes = val.to_le_bytes();
-        self.map[off..off + 4].copy_from_slice(&bytes);
+/// Copy `data` to device memory at `dst`, with the same
+/// access pattern as `copy_from_device`.
+///
+/// # Safety
+///
+/// `dst` must be valid for writes of `data.len()` bytes.
+unsafe fn copy_to_device(dst: *mut u8, data: &[u8]) {
+    let head = ((4 - dst as usize % 4) % 4).min(data.len());
+    let words = (data.len() - head) / 4;
+    for (i, b) in data[..head].iter().enumerate() {
+        ptr::write_volatile(dst.add(i), *b);
+    }
+    for w in 0..words {
+        let i = head + w * 4;
+        let word = u32::from_ne_bytes(data[i..i + 4].try_into().unwrap());
+        ptr::write_volatile(dst.add(i) as *mut u32, word);
     }
+    for (i, b) in data.iter().enumerate().skip(head + words * 4) {
+        ptr::write_volatile(dst.add(i), *b);
+    }
+}
+
+/// Bulk reads and typed slice reads for `Mmio`, `MmioRo` and `MmioRegion`.
+macro_rules! bulk_read {
+    ($ty:ty) => {
+        impl $ty {
+            /// Copy `out.len()` bytes starting at physical `addr` into `out`,
+            /// using only aligned accesses (see `write_from`).
+            pub fn read_into(&self, addr: u64, out: &mut [u8]) -> Result<()> {
+                let src = self.span(addr, out.len())?;
+                // SAFETY: `span` checked the whole range.
+                unsafe { copy_from_device(src, out) };
+                Ok(())
+            }
+
+            /// Read `out.len()` consecutive values starting at physical
+            /// `addr`, one access of `T`'s width each (two 32-bit accesses
+            /// per 64-bit value on 32-bit targets, see `read_u64`).
+            pub fn read_slice<T: MmioValue>(&self, addr: u64, out: &mut [T]) -> Result<()> {
+                let size = size_of::<T>();
+                self.check_slice(addr, size, out.len())?;
+                for (i, v) in out.iter_mut().enumerate() {
+                    *v = T::read_from(self, addr + (i * size) as u64)?;
+                }
+                Ok(())
+            }
+
+            /// Check a whole typed slice up front, so a bad one fails before
+            /// anything is accessed.
+            fn check_slice(&self, addr: u64, size: usize, count: usize) -> Result<()> {
+                let n = size.checked_mul(count).ok_or(MmioError::OutOfRange { addr, base: self.base, len: self.len() })?;
+                let ptr = self.span(addr, n)?;
+                if !addr.is_multiple_of(size as u64) || !(ptr as usize).is_multiple_of(size) {
+                    return Err(MmioError::Misaligned { addr, align: size });
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+/// Bulk writes and typed slice writes for `Mmio` and `MmioRegion`.
+macro_rules! bulk_write {
+    ($ty:ty) => {
+        impl $ty {
+            /// Copy `data` to physical `addr`. Unaligned ends are written a
+            /// byte at a time and the rest as aligned 32-bit words, so the copy
+            /// is safe on ARM device mappings. The whole range is checked first.
+            pub fn write_from(&mut self, addr: u64, data: &[u8]) -> Result<()> {
+                let dst = self.span(addr, data.len())?;
+                // SAFETY: `span` checked the whole range.
+                unsafe { copy_to_device(dst, data) };
+                Ok(())
+            }
+
+            /// Set `len` bytes at physical `addr` to `byte`.
+            pub fn fill(&mut self, addr: u64, len: usize, byte: u8) -> Result<()> {
+                let dst = self.span(addr, len)?;
+                let chunk = [byte; 256];
+                for start in (0..len).step_by(chunk.len()) {
+                    let n = (len - start).min(chunk.len());
+                    // SAFETY: `span` checked the whole range.
+                    unsafe { copy_to_device(dst.add(start), &chunk[..n]) };
+                }
+                Ok(())
+            }
+
+            /// Write `data` as consecutive values starting at physical `addr`,
+            /// one access of `T`'s width each (two 32-bit accesses per 64-bit
+            /// value on 32-bit targets, see `write_u64`).
+            pub fn write_slice<T: MmioValue>(&mut self, addr: u64, data: &[T]) -> Result<()> {
+                let size = size_of::<T>();
+                self.check_slice(addr, size, data.len())?;
+                for (i, v) in data.iter().enumerate() {
+                    v.write_to(self, addr + (i * size) as u64)?;
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+bulk_read!(Mmio);
+bulk_read!(MmioRo);
+bulk_read!(MmioRegion);
+bulk_write!(Mmio);
+bulk_write!(MmioRegion);
+
+/// Little-endian read accessors of one width for `Mmio` and `MmioRo`.
+macro_rules! read_accessors {
+    ($ty:ty, $t:ty, $bits:literal, $try_read:ident, $read:ident $(, #[$note:meta])*) => {
+        impl $ty {
+            #[doc = concat!("Read the ", $bits, "-bit value at physical `addr`.")]
+            $(#[$note])*
+            pub fn $try_read(&self, addr: u64) -> Result<$t> {
+                self.read_volatile::<$t>(addr).map(<$t>::from_le)
+            }
+
+            #[doc = concat!("Read the ", $bits, "-bit value at physical `addr`.")]
+            $(#[$note])*
+            ///
+            /// # Panics
+            ///
+            /// If `addr` is outside the mapping or not naturally aligned; use
+            #[doc = concat!("`", stringify!($try_read), "` where the address is not known to be valid.")]
+            pub fn $read(&self, addr: u64) -> $t {
+                self.$try_read(addr).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+/// Little-endian write accessors of one width for `Mmio` and `MmioRegion`.
+macro_rules! write_accessors {
+    ($ty:ty, $t:ty, $bits:literal, $try_write:ident, $write:ident $(, #[$note:meta])*) => {
+        impl $ty {
+            #[doc = concat!("Write the ", $bits, "-bit value at physical `addr`.")]
+            $(#[$note])*
+            pub fn $try_write(&mut self, addr: u64, val: $t) -> Result<()> {
+                self.write_volatile::<$t>(addr, val.to_le())
+            }
+
+            #[doc = concat!("Write the ", $bits, "-bit value at physical `addr`.")]
+            $(#[$note])*
+            ///
+            /// # Panics
+            ///
+            /// If `addr` is outside the mapping or not naturally aligned; use
+            #[doc = concat!("`", stringify!($try_write), "` where the address is not known to be valid.")]
+            pub fn $write(&mut self, addr: u64, val: $t) {
+                self.$try_write(addr, val).unwrap_or_else(|e| panic!("{}", e))
+            }
+        }
+    };
+}
+
+/// Little-endian accessors of one width. Each access is a single volatile
+/// load or store of that width, never merged or elided; only 64-bit accesses
+/// on 32-bit targets are split (see the note on them).
+macro_rules! accessors {
+    ($t:ty, $bits:literal, $try_read:ident, $try_write:ident, $read:ident, $write:ident $(, #[$note:meta])*) => {
+        read_accessors!(Mmio, $t, $bits, $try_read, $read $(, #[$note])*);
+        read_accessors!(MmioRo, $t, $bits, $try_read, $read $(, #[$note])*);
+        read_accessors!(MmioRegion, $t, $bits, $try_read, $read $(, #[$note])*);
+        write_accessors!(Mmio, $t, $bits, $try_write, $write $(, #[$note])*);
+        write_accessors!(MmioRegion, $t, $bits, $try_write, $write $(, #[$note])*);
+    };
+}
+
+accessors!(u8, "8", try_read_u8, try_write_u8, read_u8, write_u8);
+accessors!(u16, "16", try_read_u16, try_write_u16, read_u16, write_u16);
+accessors!(u32, "32", try_read_u32, try_write_u32, read_u32, write_u32);
+accessors!(
+    u64, "64", try_read_u64, try_write_u64, read_u64, write_u64,
+    #[doc = ""],
+    #[doc = "On 32-bit targets (e.g. the Cortex-A8/A9/A15 hosts of AM335x, AM437x"],
+    #[doc = "and AM57xx) this is two 32-bit accesses in unspecified order, so it is"],
+    #[doc = "not atomic with respect to the PRU; use two 32-bit accesses in the order"],
+    #[doc = "the device needs where that matters."]
+);
+
+/// Order all earlier host stores to the mapping before any later ones, e.g.
+/// fill a buffer in PRU DRAM, then `write_barrier()`, then write the flag or
+/// register that tells the PRU to consume it.
+pub fn write_barrier() {
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    // SAFETY: a barrier instruction has no operands and no other effect.
+    unsafe {
+        std::arch::asm!("dsb st", options(nostack, preserves_flags))
+    };
+    #[cfg(not(any(target_arch = "arm", target_arch = "aarch64")))]
+    std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+/// Order all earlier host loads and stores to the mapping before any later
+/// ones, e.g. between seeing a PRU "done" flag and reading its results.
+pub fn barrier() {
+    #[cfg(any(target_arch = "arm", target_arch = "aarch64"))]
+    // SAFETY: as for `write_barrier`.
+    unsafe {
+        std::arch::asm!("dsb sy", options(nostack, preserves_flags))
+    };
+    #[cfg(not(any(target_arch = "arm", target_arch = "aarch64")))]
+    std::sync::atomic::fence(std::sync::atomic::Ordering::SeqCst);
+}
+
+fn page_size() -> u64 {
+    // SAFETY: sysconf has no memory-safety preconditions.
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        n if n > 0 => n as u64,
+        _ => 4096,
+    }
+}
+
+/// Read a `0x`-prefixed hex sysfs attribute.
+fn read_hex_attr(path: &Path) -> Result<u64> {
+    let raw = fs::read_to_string(path)?;
+    let value = raw.trim();
+    u64::from_str_radix(value.trim_start_matches("0x"), 16)
+        .map_err(|_| MmioError::BadAttr { path: path.to_path_buf(), value: value.to_string() })
+}
+
+/// Values that can be read from and written to a `MemoryBus` at a physical address.
+pub trait MmioValue: Copy {
+    fn read_from<B: MemoryBus + ?Sized>(bus: &B, addr: u64) -> Result<Self>;
+    fn write_to<B: MemoryBus + ?Sized>(self, bus: &mut B, addr: u64) -> Result<()>;
+}
+
+macro_rules! mmio_value {
+    ($($t:ty => $raw:ty, $read:ident, $write:ident;)*) => {
+        $(
+            impl MmioValue for $t {
+                fn read_from<B: MemoryBus + ?Sized>(bus: &B, addr: u64) -> Result<Self> {
+                    bus.$read(addr).map(|v| v as $t)
+                }
+
+                fn write_to<B: MemoryBus + ?Sized>(self, bus: &mut B, addr: u64) -> Result<()> {
+                    bus.$write(addr, self as $raw)
+                }
+            }
+        )*
+    };
+}
+
+mmio_value! {
+    u8 => u8, try_read_u8, try_write_u8;
+    i8 => u8, try_read_u8, try_write_u8;
+    u16 => u16, try_read_u16, try_write_u16;
+    i16 => u16, try_read_u16, try_write_u16;
+    u32 => u32, try_read_u32, try_write_u32;
+    i32 => u32, try_read_u32, try_write_u32;
+    u64 => u64, try_read_u64, try_write_u64;
+    i64 => u64, try_read_u64, try_write_u64;
 }
 
 pub type Result<T> = std::result::Result<T, MmioError>;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    impl Mmio {
+        /// Anonymous memory standing in for `len` bytes at physical `base`.
+        pub(crate) fn anon(base: u64, len: usize) -> Mmio {
+            let page_offset = (base as usize) & (4096 - 1);
+            let map = MmapMut::map_anon(len + page_offset).unwrap();
+            Mmio { map: Mapping::new(map), base, page_offset }
+        }
+    }
+
+    #[test]
+    fn checks_bounds_and_alignment() {
+        let mut m = Mmio::anon(0x4a30_2010, 0x10);
+        m.try_write_u32(0x4a30_201c, 0xdead_beef).unwrap();
+        assert_eq!(m.read_u32(0x4a30_201c), 0xdead_beef);
+        assert_eq!(m.read_u8(0x4a30_201c), 0xef);
+
+        for addr in [0x4a30_200c, 0x4a30_2020, u64::MAX - 1] {
+            match m.try_read_u32(addr) {
+                Err(MmioError::OutOfRange { addr: a, base: 0x4a30_2010, len: 0x10 }) => assert_eq!(a, addr),
+                other => panic!("expected OutOfRange for {:#x}, got {:?}", addr, other),
+            }
+        }
+        assert!(matches!(m.try_write_u32(u64::MAX, 0), Err(MmioError::OutOfRange { .. })));
+        assert!(matches!(m.try_read_u32(0x4a30_2012), Err(MmioError::Misaligned { addr: 0x4a30_2012, align: 4 })));
+    }
+
+    #[test]
+    fn accesses_each_width() {
+        let mut m = Mmio::anon(0x1000, 0x10);
+        m.write_u64(0x1008, 0x0102_0304_0506_0708);
+        assert_eq!(m.read_u32(0x1008), 0x0506_0708);
+        assert_eq!(m.read_u16(0x100e), 0x0102);
+        assert_eq!(m.read_u8(0x1009), 0x07);
+        m.write_u8(0x1000, 0xff);
+        assert_eq!(i8::read_from(&m, 0x1000).unwrap(), -1);
+        (-2i16).write_to(&mut m, 0x1002).unwrap();
+        assert_eq!(m.read_u16(0x1002), 0xfffe);
+        assert!(matches!(m.try_read_u64(0x1004), Err(MmioError::Misaligned { align: 8, .. })));
+        assert!(matches!(m.try_write_u16(0x1010, 0), Err(MmioError::OutOfRange { .. })));
+        write_barrier();
+        barrier();
+    }
+
+    #[test]
+    fn bulk_copies_check_bounds() {
+        let mut m = Mmio::anon(0x4a30_0000, 0x1000);
+        let table: Vec<u8> = (0..=255u8).cycle().take(0x1000 - 3).collect();
+        m.write_from(0x4a30_0003, &table).unwrap();
+        assert_eq!(m.read_u8(0x4a30_0003), 0);
+        assert_eq!(m.read_u32(0x4a30_0004), u32::from_le_bytes([1, 2, 3, 4]));
+
+        let mut back = vec![0u8; table.len()];
+        m.read_into(0x4a30_0003, &mut back).unwrap();
+        assert_eq!(back, table);
+        let mut odd = [0u8; 3];
+        m.read_into(0x4a30_0005, &mut odd).unwrap();
+        assert_eq!(odd, [2, 3, 4]);
+
+        m.fill(0x4a30_0001, 0x301, 0xa5).unwrap();
+        assert_eq!(m.read_u8(0x4a30_0000), 0);
+        assert_eq!(m.read_u32(0x4a30_0100), 0xa5a5_a5a5);
+        assert_eq!(m.read_u8(0x4a30_0301), 0xa5);
+        assert_eq!(m.read_u8(0x4a30_0302), table[0x2ff]);
+
+        assert!(matches!(m.write_from(0x4a30_0ffe, &[0; 3]), Err(MmioError::OutOfRange { .. })));
+        assert!(matches!(m.read_into(0x4a2f_ffff, &mut [0; 2]), Err(MmioError::OutOfRange { .. })));
+        assert_eq!(m.read_u16(0x4a30_0ffe), u16::from_le_bytes([table[0xffb], table[0xffc]]));
+    }
+
+    #[test]
+    fn typed_slices() {
+        let mut m = Mmio::anon(0x1000, 0x100);
+        let words: Vec<u32> = (0..16).map(|i| i * 0x0101_0101).collect();
+        m.write_slice(0x1040, &words).unwrap();
+        assert_eq!(m.read_u32(0x107c), 15 * 0x0101_0101);
+        let mut back = [0u32; 16];
+        m.read_slice(0x1040, &mut back).unwrap();
+        assert_eq!(back[..], words[..]);
+        let mut halves = [0i16; 2];
+        m.read_slice(0x1044, &mut halves).unwrap();
+        assert_eq!(halves, [0x0101, 0x0101]);
+
+        assert!(matches!(m.write_slice(0x1042, &words[..1]), Err(MmioError::Misaligned { .. })));
+        // rejected before anything is written
+        assert!(matches!(m.write_slice(0x10f0, &[u32::MAX; 8]), Err(MmioError::OutOfRange { .. })));
+        assert_eq!(m.read_u32(0x10f0), 0);
+    }
+
+    #[test]
+    fn splits_disjoint_regions() {
+        fn assert_send<T: Send>(_: &T) {}
+
+        let mut m = Mmio::anon(0x4a30_0000, 0x3_0000);
+        let mut dram0 = m.split_region(0, 0x2000).unwrap();
+        let intc = m.split_region(0x2_0000, 0x2000).unwrap();
+        assert_send(&intc);
+        assert!(matches!(m.split_region(0x1000, 0x2000), Err(MmioError::RegionInUse { .. })));
+        assert!(matches!(m.split_region(0x2_f000, 0x2000), Err(MmioError::OutOfRange { .. })));
+
+        let t = std::thread::spawn(move || {
+            let mut intc = intc;
+            intc.write_u32(0x4a32_0010, 0xaa);
+            assert!(intc.try_read_u32(0x4a32_2000).is_err());
+            intc.read_u32(0x4a32_0010)
+        });
+        dram0.write_u32(0x4a30_0000, 0x55);
+        assert_eq!(t.join().unwrap(), 0xaa);
+
+        assert!(matches!(m.try_read_u32(0x4a30_0000), Err(MmioError::Claimed { .. })));
+        m.write_u32(0x4a30_2000, 1);
+        drop(m);
+        // the region keeps the mapping alive after the parent is gone
+        assert_eq!(dram0.read_u32(0x4a30_0000), 0x55);
+    }
+
+    #[test]
+    fn parent_regains_released_range() {
+        let mut m = Mmio::anon(0x1000, 0x100);
+        let r = m.split_region(0x10, 0x10).unwrap();
+        assert!(m.try_read_u32(0x1010).is_err());
+        drop(r);
+        assert_eq!(m.read_u32(0x1010), 0);
+        m.split_region(0x10, 0x10).unwrap();
+    }
+
+    #[test]
+    fn dropping_empty_region_keeps_others_claimed() {
+        let mut m = Mmio::anon(0x1000, 0x100);
+        let empty = m.split_region(0x10, 0).unwrap();
+        let r = m.split_region(0x10, 0x10).unwrap();
+        drop(empty);
+        // `r` still owns its range: neither the parent nor a new region may touch it
+        assert!(matches!(m.try_read_u32(0x1010), Err(MmioError::Claimed { .. })));
+        assert!(matches!(m.split_region(0x10, 0x10), Err(MmioError::RegionInUse { .. })));
+        drop(r);
+        m.split_region(0x10, 0x10).unwrap();
+    }
+
+    #[test]
+    fn maps_file_with_offset() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, &[0u8; 0x3000]).unwrap();
+        let mut m = Mmio::map_file(file.path(), 0x2010, 0x100).unwrap();
+        m.write_u32(0x2010, 0x1234_5678);
+        let mut m = m.rebase(0x4a30_0000);
+        assert_eq!(m.read_u32(0x4a30_0000), 0x1234_5678);
+        m.write_u16(0x4a30_00fe, 0xabcd);
+        assert!(m.try_read_u8(0x4a30_0100).is_err());
+        drop(m);
+        let bytes = fs::read(file.path()).unwrap();
+        assert_eq!(bytes[0x2010..0x2014], 0x1234_5678u32.to_le_bytes());
+        assert_eq!(bytes[0x210e..0x2110], 0xabcdu16.to_le_bytes());
+    }
+
+    #[test]
+    fn rebase_checks_alignment_in_mapping() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let data: Vec<u8> = (0..0x40).collect();
+        std::io::Write::write_all(&mut file, &data).unwrap();
+        // file offset 2 stands in for a word-aligned address
+        let m = Mmio::map_file(file.path(), 2, 0x20).unwrap().rebase(0x4a30_0000);
+        assert!(matches!(m.try_read_u32(0x4a30_0000), Err(MmioError::Misaligned { align: 4, .. })));
+        assert_eq!(m.read_u16(0x4a30_0002), u16::from_le_bytes([4, 5]));
+        let mut out = [0u8; 9];
+        m.read_into(0x4a30_0001, &mut out).unwrap();
+        assert_eq!(out, data[3..12]);
+        let mut words = [0u32; 2];
+        assert!(matches!(m.read_slice(0x4a30_0000, &mut words), Err(MmioError::Misaligned { .. })));
+    }
+
+    #[test]
+    fn map_file_checks_file_length() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut file, &[0u8; 0x100]).unwrap();
+        assert!(Mmio::map_file(file.path(), 0x80, 0x80).is_ok());
+        assert!(matches!(
+            Mmio::map_file(file.path(), 0x80, 0x81),
+            Err(MmioError::PastEndOfFile { offset: 0x80, len: 0x81, size: 0x100, .. })
+        ));
+        assert!(matches!(Mmio::map_file_readonly(file.path(), 0x100, 4), Err(MmioError::PastEndOfFile { .. })));
+    }
+
+    #[test]
+    fn maps_file_readonly() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        let mut data = vec![0u8; 0x100];
+        data[0x40..0x48].copy_from_slice(&0x1122_3344_5566_7788u64.to_le_bytes());
+        std::io::Write::write_all(&mut file, &data).unwrap();
+        // a read-only file is enough: the mapping is opened O_RDONLY
+        let mut perms = fs::metadata(file.path()).unwrap().permissions();
+        perms.set_readonly(true);
+        fs::set_permissions(file.path(), perms).unwrap();
+
+        let m = Mmio::map_file_readonly(file.path(), 0x40, 0x40).unwrap().rebase(0x4a31_0000);
+        assert_eq!(m.read_u64(0x4a31_0000), 0x1122_3344_5566_7788);
+        assert_eq!(m.read_u16(0x4a31_0006), 0x1122);
+        assert!(matches!(m.try_read_u32(0x4a31_0040), Err(MmioError::OutOfRange { .. })));
+        let mut words = [0u32; 2];
+        m.read_slice(0x4a31_0000, &mut words).unwrap();
+        assert_eq!(words, [0x5566_7788, 0x1122_3344]);
+
+        // usable where a `MemoryBus` is expected, refusing writes
+        let mut m = m;
+        let bus: &mut dyn MemoryBus = &mut m;
+        assert_eq!(u64::read_from(bus, 0x4a31_0000).unwrap(), 0x1122_3344_5566_7788);
+        assert!(matches!(bus.try_write_u32(0x4a31_0000, 0), Err(MmioError::ReadOnly { addr: 0x4a31_0000 })));
+        assert!(matches!(bus.write_bytes(0x4a31_0001, b"x"), Err(MmioError::ReadOnly { .. })));
+        assert_eq!(m.read_u64(0x4a31_0000), 0x1122_3344_5566_7788);
+    }
+
+    #[test]
+    fn maps_uio_region() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = SysRoot::new(dir.path());
+        let map1 = root.resolve(SYS_UIO).join("uio0/maps/map1");
+        fs::create_dir_all(&map1).unwrap();
+        fs::write(map1.join("addr"), "0x4a310000\n").unwrap();
+        fs::write(map1.join("size"), "0x00003000\n").unwrap();
+        fs::write(map1.join("offset"), "0x0\n").unwrap();
+        fs::create_dir_all(root.resolve(DEV)).unwrap();
+        let page = page_size() as usize;
+        let mut dev = vec![0u8; page + 0x3000];
+        dev[page + 0x10] = 0x5a;
+        fs::write(root.resolve(DEV).join("uio0"), &dev).unwrap();
+
+        let mut m = Mmio::map_uio_in(&root, "uio0", 1).unwrap();
+        assert_eq!((m.base(), m.len()), (0x4a31_0000, 0x3000));
+        assert_eq!(m.read_u8(0x4a31_0010), 0x5a);
+        m.write_u32(0x4a31_2ffc, 1);
+        assert!(matches!(Mmio::map_uio_in(&root, "uio0", 0), Err(MmioError::Io(_))));
+    }
+
+    #[test]
+    fn map_checked_in_fake_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = SysRoot::new(dir.path());
+        fs::create_dir_all(root.resolve("/proc")).unwrap();
+        fs::write(root.resolve(iomem::PROC_IOMEM), "4a300000-4a37ffff : 4a300000.pruss\n").unwrap();
+        fs::create_dir_all(root.resolve(DEV)).unwrap();
+        // sparse stand-in for /dev/mem covering the PRUSS
+        File::create(root.resolve(DEV_MEM)).unwrap().set_len(0x4a38_0000).unwrap();
+
+        let mut m = Mmio::map_checked_in(&root, 0x4a30_2000, 0x2000).unwrap();
+        m.write_u32(0x4a30_2000, 0xfeed);
+        assert_eq!(m.read_u32(0x4a30_2000), 0xfeed);
+        assert!(matches!(Mmio::map_checked_in(&root, 0x4a40_0000, 4), Err(MmioError::Unclaimed { .. })));
+    }
+
+    #[test]
+    #[should_panic(expected = "outside mapping")]
+    fn read_u32_panics_out_of_range() {
+        Mmio::anon(0x1000, 4).read_u32(0x1004);
+    }
+}
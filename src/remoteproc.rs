@@ -1,25 +1,101 @@
+use std::fmt;
 use std::fs;
-use std::io::{self, Write};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 
 use thiserror::Error;
 
+use crate::cores::{self, CoreId};
+use crate::firmware_store::{FirmwareStore, FirmwareStoreError};
+use crate::guard::RunningProc;
+use crate::lock::{self, CoreLock, LockMode, RUN_LOCK_DIR};
+use crate::sysroot::SysRoot;
+use crate::trace::TraceFollow;
+
 const SYS_REMOTEPROC: &str = "/sys/class/remoteproc";
+const DEBUGFS_REMOTEPROC: &str = "/sys/kernel/debug/remoteproc";
+
+/// First and longest interval between `state` re-reads in `wait_for_state`.
+const WAIT_POLL_MIN: Duration = Duration::from_millis(1);
+const WAIT_POLL_MAX: Duration = Duration::from_millis(100);
+
+/// How long `deploy_and_start` and `start_guarded_with` wait for each state change.
+const DEPLOY_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Error)]
 pub enum RemoteProcError {
     #[error("io: {0}")]
     Io(#[from] io::Error),
-    #[error("invalid state data")]
-    InvalidState,
+    #[error("invalid state transition from {current} to {target}")]
+    InvalidState {
+        current: RemoteProcState,
+        target: RemoteProcState,
+    },
+    #[error("kernel rejected '{value}' written to {attr}: {reason}")]
+    Rejected {
+        attr: String,
+        value: String,
+        reason: WriteRejection,
+    },
+    #[error("unexpected value '{value}' in {attr}")]
+    UnexpectedValue { attr: String, value: String },
+    #[error("firmware store: {0}")]
+    Store(#[from] FirmwareStoreError),
+    #[error("timed out waiting for state {target} (last seen: {last})")]
+    Timeout {
+        target: RemoteProcState,
+        last: RemoteProcState,
+    },
+}
+
+/// Why the kernel refused a sysfs attribute write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WriteRejection {
+    /// `EBUSY`: the remoteproc is already running or in use.
+    Busy,
+    /// `EINVAL`: the request is not valid in the current state (or the value is malformed).
+    Invalid,
+    /// `EPERM`: the operation is not permitted for this remoteproc.
+    NotPermitted,
+}
+
+impl WriteRejection {
+    fn from_errno(errno: i32) -> Option<Self> {
+        match errno {
+            libc::EBUSY => Some(WriteRejection::Busy),
+            libc::EINVAL => Some(WriteRejection::Invalid),
+            libc::EPERM => Some(WriteRejection::NotPermitted),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WriteRejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            WriteRejection::Busy => "busy (EBUSY)",
+            WriteRejection::Invalid => "invalid (EINVAL)",
+            WriteRejection::NotPermitted => "not permitted (EPERM)",
+        };
+        f.write_str(s)
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RemoteProcState {
     Offline,
     Booting,
+    /// Firmware is running. The kernel reports this as `running` (older trees used `online`).
     Online,
     Suspended,
+    Crashed,
+    /// Running firmware that the kernel attached to rather than booted.
+    Attached,
+    Detached,
+    Deleted,
+    Invalid,
     Unknown(String),
 }
 
@@ -28,23 +104,150 @@ impl RemoteProcState {
         match s.trim() {
             "offline" => RemoteProcState::Offline,
             "booting" => RemoteProcState::Booting,
-            "online" => RemoteProcState::Online,
+            "online" | "running" => RemoteProcState::Online,
             "suspended" => RemoteProcState::Suspended,
+            "crashed" => RemoteProcState::Crashed,
+            "attached" => RemoteProcState::Attached,
+            "detached" => RemoteProcState::Detached,
+            "deleted" => RemoteProcState::Deleted,
+            "invalid" => RemoteProcState::Invalid,
             other => RemoteProcState::Unknown(other.to_string()),
         }
     }
+
+    /// Return the kernel's name for this state.
+    pub fn as_str(&self) -> &str {
+        match self {
+            RemoteProcState::Offline => "offline",
+            RemoteProcState::Booting => "booting",
+            RemoteProcState::Online => "running",
+            RemoteProcState::Suspended => "suspended",
+            RemoteProcState::Crashed => "crashed",
+            RemoteProcState::Attached => "attached",
+            RemoteProcState::Detached => "detached",
+            RemoteProcState::Deleted => "deleted",
+            RemoteProcState::Invalid => "invalid",
+            RemoteProcState::Unknown(s) => s,
+        }
+    }
+
+    /// Return the attribute and command that move a remoteproc from `self`
+    /// to `target`, or `None` when the kernel does not allow that transition.
+    ///
+    /// A crashed core only accepts `recover` on `recovery`: `state` refuses
+    /// `stop` unless the core is running or attached, and ignores `start`.
+    fn command_to(&self, target: &RemoteProcState) -> Option<(&'static str, &'static str)> {
+        use RemoteProcState::*;
+        match (self, target) {
+            (Offline, Online) => Some(("state", "start")),
+            (Crashed, Online) => Some(("recovery", "recover")),
+            (Detached, Attached) => Some(("state", "start")),
+            (Online | Attached, Offline) => Some(("state", "stop")),
+            (Attached, Detached) => Some(("state", "detach")),
+            _ => None,
+        }
+    }
+
+    /// Return true if `transition(target)` is allowed from this state.
+    pub fn can_transition_to(&self, target: &RemoteProcState) -> bool {
+        self == target || self.command_to(target).is_some()
+    }
+}
+
+impl fmt::Display for RemoteProcState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The `recovery` attribute: whether the kernel restarts a crashed core.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    Enabled,
+    Disabled,
+}
+
+impl Recovery {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "enabled" => Some(Recovery::Enabled),
+            "disabled" => Some(Recovery::Disabled),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Recovery::Enabled => "enabled",
+            Recovery::Disabled => "disabled",
+        }
+    }
+}
+
+/// The `coredump` attribute: how a crash dump is collected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoredumpMode {
+    /// Copy the segments into a devcoredump.
+    Default,
+    /// Expose the segments in place; the core waits until the dump is read.
+    Inline,
+    Disabled,
+}
+
+impl CoredumpMode {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "default" | "enabled" => Some(CoredumpMode::Default),
+            "inline" => Some(CoredumpMode::Inline),
+            "disabled" => Some(CoredumpMode::Disabled),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CoredumpMode::Default => "default",
+            CoredumpMode::Inline => "inline",
+            CoredumpMode::Disabled => "disabled",
+        }
+    }
+}
+
+/// Snapshot of a remoteproc instance's sysfs attributes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteProcInfo {
+    /// Instance directory name, e.g. `remoteproc1`.
+    pub instance: String,
+    /// The `name` attribute, e.g. `4a334000.pru`.
+    pub name: String,
+    pub state: RemoteProcState,
+    pub firmware: String,
+    /// `recovery` attribute, when the kernel exposes it.
+    pub recovery: Option<Recovery>,
+    /// `coredump` attribute, when the kernel exposes it.
+    pub coredump: Option<CoredumpMode>,
+    /// Resolved `device` link.
+    pub device: Option<PathBuf>,
+    /// Resolved `device/of_node` link.
+    pub of_node: Option<PathBuf>,
 }
 
 pub struct RemoteProc {
     path: PathBuf,
+    root: SysRoot,
 }
 
 impl RemoteProc {
     pub fn list() -> Result<Vec<String>> {
+        Self::list_in(&SysRoot::host())
+    }
+
+    /// List remoteproc instances under `root` (see `SysRoot`).
+    pub fn list_in(root: &SysRoot) -> Result<Vec<String>> {
         let mut out = Vec::new();
-        let base = Path::new(SYS_REMOTEPROC);
+        let base = root.resolve(SYS_REMOTEPROC);
         if base.exists() {
-            for entry in fs::read_dir(base)? {
+            for entry in fs::read_dir(&base)? {
                 let e = entry?;
                 if let Some(name) = e.file_name().to_str() {
                     out.push(name.to_string());
@@ -54,15 +257,170 @@ impl RemoteProc {
         Ok(out)
     }
 
+    /// List remoteproc instances with their attributes, sorted by instance name.
+    pub fn list_info() -> Result<Vec<RemoteProcInfo>> {
+        Self::list_info_in(&SysRoot::host())
+    }
+
+    /// Same as `list_info`, under `root` (see `SysRoot`).
+    pub fn list_info_in(root: &SysRoot) -> Result<Vec<RemoteProcInfo>> {
+        let mut names = Self::list_in(root)?;
+        names.sort();
+        names.iter().map(|n| Self::open_in(root, n)?.info()).collect()
+    }
+
+    /// Find the remoteproc driving PRU `core` of PRU-ICSS `icss`.
+    /// Shorthand for `open_core(CoreId::pru(icss, core))`.
+    pub fn find_pru(icss: usize, core: usize) -> Result<Self> {
+        Self::find_pru_in(&SysRoot::host(), icss, core)
+    }
+
+    /// Same as `find_pru`, under `root` (see `SysRoot`).
+    pub fn find_pru_in(root: &SysRoot, icss: usize, core: usize) -> Result<Self> {
+        Self::open_core_in(root, CoreId::pru(icss, core))
+    }
+
+    /// Open the remoteproc for core `id`, as numbered by `cores::discover`.
+    pub fn open_core(id: CoreId) -> Result<Self> {
+        Self::open_core_in(&SysRoot::host(), id)
+    }
+
+    /// Same as `open_core`, under `root` (see `SysRoot`).
+    pub fn open_core_in(root: &SysRoot, id: CoreId) -> Result<Self> {
+        match cores::discover(root)?.into_iter().find(|c| c.id == id) {
+            Some(c) => Self::open_in(root, &c.info.instance),
+            None => Err(RemoteProcError::Io(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no remoteproc for core {}", id),
+            ))),
+        }
+    }
+
     pub fn open(name: &str) -> Result<Self> {
-        let path = Path::new(SYS_REMOTEPROC).join(name);
+        Self::open_in(&SysRoot::host(), name)
+    }
+
+    /// Open a remoteproc instance by name under `root` (see `SysRoot`).
+    pub fn open_in(root: &SysRoot, name: &str) -> Result<Self> {
+        let path = root.resolve(SYS_REMOTEPROC).join(name);
         if !path.exists() {
             return Err(RemoteProcError::Io(io::Error::new(
                 io::ErrorKind::NotFound,
                 format!("remoteproc '{}' not found", name),
             )));
         }
-        Ok(RemoteProc { path })
+        Ok(RemoteProc { path, root: root.clone() })
+    }
+
+    /// Return the sysfs directory of this remoteproc.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Return the root this remoteproc was opened under.
+    pub fn sysroot(&self) -> &SysRoot {
+        &self.root
+    }
+
+    /// Path of this core's lock file (`/run/pru_rproc_user/<instance>.lock`).
+    pub fn lock_path(&self) -> PathBuf {
+        self.root.resolve(RUN_LOCK_DIR).join(format!("{}.lock", self.instance()))
+    }
+
+    /// Take the advisory per-core lock shared by every process using this
+    /// crate. `timeout` of `None` blocks; on timeout the error names the
+    /// holder's PID when known.
+    pub fn lock(&self, mode: LockMode, timeout: Option<Duration>) -> lock::Result<CoreLock> {
+        CoreLock::acquire_in(&self.root, self.lock_path(), mode, timeout)
+    }
+
+    /// Return the instance directory name (e.g. `remoteproc1`).
+    pub fn instance(&self) -> &str {
+        self.path.file_name().and_then(|n| n.to_str()).unwrap_or("")
+    }
+
+    /// Path of trace buffer `index` in debugfs (`.../remoteprocN/traceN`).
+    pub fn trace_path(&self, index: usize) -> PathBuf {
+        self.root
+            .resolve(DEBUGFS_REMOTEPROC)
+            .join(self.instance())
+            .join(format!("trace{}", index))
+    }
+
+    /// Read the current contents of the first trace buffer. Requires debugfs
+    /// to be mounted and firmware that declares a trace resource.
+    pub fn trace(&self) -> Result<String> {
+        let bytes = fs::read(self.trace_path(0))?;
+        let end = bytes.iter().position(|b| *b == 0).unwrap_or(bytes.len());
+        Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+    }
+
+    /// Follow the first trace buffer, yielding only lines written from now
+    /// on. The buffer is re-read every `interval`.
+    pub fn trace_follow(&self, interval: Duration) -> Result<TraceFollow> {
+        TraceFollow::new(self.trace_path(0), interval)
+    }
+
+    /// Async variant of `trace_follow`.
+    #[cfg(feature = "async")]
+    pub async fn trace_follow_async(&self, interval: Duration) -> Result<crate::trace::async_impl::AsyncTraceFollow> {
+        crate::trace::async_impl::AsyncTraceFollow::new(self.trace_path(0), interval).await
+    }
+
+    /// Read the instance's attributes into a `RemoteProcInfo`.
+    pub fn info(&self) -> Result<RemoteProcInfo> {
+        Ok(RemoteProcInfo {
+            instance: self.instance().to_string(),
+            name: self.read_attr("name")?.trim().to_string(),
+            state: self.state()?,
+            firmware: self.firmware()?,
+            recovery: self.recovery_opt()?,
+            coredump: self.coredump_opt()?,
+            device: fs::canonicalize(self.path.join("device")).ok(),
+            of_node: fs::canonicalize(self.path.join("device/of_node")).ok(),
+        })
+    }
+
+    /// Read the `recovery` attribute.
+    pub fn recovery(&self) -> Result<Recovery> {
+        self.recovery_opt()?.ok_or_else(|| missing_attr("recovery"))
+    }
+
+    /// Enable or disable automatic recovery after a crash.
+    pub fn set_recovery(&self, recovery: Recovery) -> Result<()> {
+        self.write_attr("recovery", recovery.as_str())
+    }
+
+    /// Trigger a one-off recovery of a crashed core (with recovery disabled).
+    pub fn recover(&self) -> Result<()> {
+        self.write_attr("recovery", "recover")
+    }
+
+    /// Read the `coredump` attribute.
+    pub fn coredump(&self) -> Result<CoredumpMode> {
+        self.coredump_opt()?.ok_or_else(|| missing_attr("coredump"))
+    }
+
+    /// Select how crash dumps are collected.
+    pub fn set_coredump(&self, mode: CoredumpMode) -> Result<()> {
+        self.write_attr("coredump", mode.as_str())
+    }
+
+    fn recovery_opt(&self) -> Result<Option<Recovery>> {
+        self.parse_attr_opt("recovery", Recovery::from_str)
+    }
+
+    fn coredump_opt(&self) -> Result<Option<CoredumpMode>> {
+        self.parse_attr_opt("coredump", CoredumpMode::from_str)
+    }
+
+    fn parse_attr_opt<T>(&self, attr: &str, parse: fn(&str) -> Option<T>) -> Result<Option<T>> {
+        match self.read_attr_opt(attr)? {
+            Some(v) => parse(&v)
+                .map(Some)
+                .ok_or_else(|| RemoteProcError::UnexpectedValue { attr: attr.to_string(), value: v }),
+            None => Ok(None),
+        }
     }
 
     fn read_attr(&self, attr: &str) -> Result<String> {
@@ -71,10 +429,29 @@ impl RemoteProc {
         Ok(s)
     }
 
+    /// Read an attribute that older kernels do not expose, trimmed.
+    fn read_attr_opt(&self, attr: &str) -> Result<Option<String>> {
+        match fs::read_to_string(self.path.join(attr)) {
+            Ok(s) => Ok(Some(s.trim().to_string())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(RemoteProcError::Io(e)),
+        }
+    }
+
     fn write_attr(&self, attr: &str, data: &str) -> Result<()> {
         let p = self.path.join(attr);
-        let mut f = fs::OpenOptions::new().write(true).open(p)?;
-        f.write_all(data.as_bytes())?;
+        // O_TRUNC is ignored by sysfs but keeps plain-file fake trees consistent.
+        let mut f = fs::OpenOptions::new().write(true).truncate(true).open(p)?;
+        f.write_all(data.as_bytes()).map_err(|e| {
+            match e.raw_os_error().and_then(WriteRejection::from_errno) {
+                Some(reason) => RemoteProcError::Rejected {
+                    attr: attr.to_string(),
+                    value: data.to_string(),
+                    reason,
+                },
+                None => RemoteProcError::Io(e),
+            }
+        })?;
         Ok(())
     }
 
@@ -83,6 +460,11 @@ impl RemoteProc {
         Ok(RemoteProcState::from_str(&s))
     }
 
+    /// Read the configured `firmware` file name.
+    pub fn firmware(&self) -> Result<String> {
+        Ok(self.read_attr("firmware")?.trim().to_string())
+    }
+
     /// Set the `firmware` file for the remoteproc. This writes the string
     /// firmware filename which the kernel will use when starting the remoteproc.
     pub fn set_firmware<P: AsRef<Path>>(&self, firmware: P) -> Result<()> {
@@ -90,20 +472,411 @@ impl RemoteProc {
         self.write_attr("firmware", &s)
     }
 
+    /// Stop the core, install `bytes` into `store` as the current image for
+    /// `name`, point `firmware` at the `name` link and start the core again,
+    /// waiting for each state change. Returns the installed file name.
+    ///
+    /// Because `firmware` names the link, a later `FirmwareStore::rollback`
+    /// takes effect on the next start.
+    pub fn deploy_and_start(&self, store: &FirmwareStore, name: &str, bytes: &[u8]) -> Result<String> {
+        self.stop_and_wait(DEPLOY_TIMEOUT)?;
+        let file_name = store.install_bytes(name, bytes)?;
+        self.set_firmware(name)?;
+        self.start_and_wait(DEPLOY_TIMEOUT)?;
+        Ok(file_name)
+    }
+
     /// Start the remoteproc by writing `start` to `state`.
     pub fn start(&self) -> Result<()> {
         self.write_attr("state", "start")
     }
 
+    /// Start the remoteproc and return a guard that stops it again on drop.
+    /// A core that was already running is left running by the guard.
+    pub fn start_guarded(&self) -> Result<RunningProc<'_>> {
+        if self.state()? == RemoteProcState::Online {
+            return Ok(RunningProc::already_running(self));
+        }
+        self.transition(RemoteProcState::Online)?;
+        Ok(RunningProc::new(self, None, false))
+    }
+
+    /// Load `firmware` and start the remoteproc. The returned guard stops
+    /// the core on drop, restores the previously configured firmware name and
+    /// starts the core again if it was running before.
+    pub fn start_guarded_with<P: AsRef<Path>>(&self, firmware: P) -> Result<RunningProc<'_>> {
+        let previous = self.firmware()?;
+        let was_running = self.state()? == RemoteProcState::Online;
+        self.stop_and_wait(DEPLOY_TIMEOUT)?;
+        self.set_firmware(firmware)?;
+        // from here on the guard puts the old firmware back even if starting fails
+        let guard = RunningProc::new(self, Some(previous), was_running);
+        self.transition(RemoteProcState::Online)?;
+        Ok(guard)
+    }
+
     /// Stop the remoteproc by writing `stop` to `state`.
     pub fn stop(&self) -> Result<()> {
         self.write_attr("state", "stop")
     }
 
+    /// Move the remoteproc to `target`, checking the current state first.
+    ///
+    /// Supported targets are `Online` (start, or recover a crashed core),
+    /// `Offline` (stop), `Attached` (re-attach a detached core) and
+    /// `Detached`. Returns `InvalidState` when the kernel would refuse the
+    /// transition, e.g. stopping a crashed core; does nothing when the
+    /// remoteproc is already in `target`.
+    pub fn transition(&self, target: RemoteProcState) -> Result<()> {
+        let current = self.state()?;
+        if current == target {
+            return Ok(());
+        }
+        match current.command_to(&target) {
+            Some((attr, cmd)) => self.write_attr(attr, cmd),
+            None => Err(RemoteProcError::InvalidState { current, target }),
+        }
+    }
+
+    /// Block until the remoteproc reports `target` or `timeout` elapses.
+    ///
+    /// Waits on the `state` attribute with `poll(POLLPRI)` so a kernel
+    /// `sysfs_notify` wakes us immediately; attributes that never notify are
+    /// re-read with exponential backoff (1 ms up to 100 ms). On timeout the
+    /// error carries the last state seen.
+    pub fn wait_for_state(&self, target: RemoteProcState, timeout: Duration) -> Result<()> {
+        // a timeout too large to represent never expires
+        let deadline = Instant::now().checked_add(timeout);
+        let mut watch = fs::File::open(self.path.join("state"))?;
+        let mut delay = WAIT_POLL_MIN;
+        loop {
+            // Reading the attribute also re-arms sysfs notification.
+            let mut s = String::new();
+            watch.seek(SeekFrom::Start(0))?;
+            watch.read_to_string(&mut s)?;
+            let last = RemoteProcState::from_str(&s);
+            if last == target {
+                return Ok(());
+            }
+
+            let now = Instant::now();
+            if deadline.is_some_and(|d| now >= d) {
+                return Err(RemoteProcError::Timeout { target, last });
+            }
+            let wait = deadline.map_or(delay, |d| delay.min(d - now));
+            let mut pfd = libc::pollfd {
+                fd: watch.as_raw_fd(),
+                events: libc::POLLPRI | libc::POLLERR,
+                revents: 0,
+            };
+            let res = unsafe { libc::poll(&mut pfd as *mut libc::pollfd, 1, wait.as_millis().max(1) as i32) };
+            if res < 0 {
+                let err = io::Error::last_os_error();
+                if err.kind() != io::ErrorKind::Interrupted {
+                    return Err(RemoteProcError::Io(err));
+                }
+            }
+            delay = (delay * 2).min(WAIT_POLL_MAX);
+        }
+    }
+
+    /// Start the remoteproc and wait until it reports `Online`.
+    pub fn start_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.transition(RemoteProcState::Online)?;
+        self.wait_for_state(RemoteProcState::Online, timeout)
+    }
+
+    /// Stop the remoteproc and wait until it reports `Offline`.
+    pub fn stop_and_wait(&self, timeout: Duration) -> Result<()> {
+        self.transition(RemoteProcState::Offline)?;
+        self.wait_for_state(RemoteProcState::Offline, timeout)
+    }
+
     /// Remove/unbind the remoteproc device (write 'remove').
     pub fn remove(&self) -> Result<()> {
         self.write_attr("state", "remove")
     }
 }
 
+fn missing_attr(attr: &str) -> RemoteProcError {
+    RemoteProcError::Io(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("attribute '{}' not supported by this kernel", attr),
+    ))
+}
+
 pub type Result<T> = std::result::Result<T, RemoteProcError>;
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread::JoinHandle;
+
+    /// Stands in for the kernel on a fake tree: turns `start`/`stop` written
+    /// to the given `state` files into `running`/`offline` until dropped.
+    /// Like the kernel it only stops a running or attached core and only
+    /// starts an offline one, leaving the state alone otherwise, and brings a
+    /// `crashed` core back when `recover` is written to its `recovery`.
+    pub(crate) struct FakeKernel {
+        done: Arc<AtomicBool>,
+        handle: Option<JoinHandle<()>>,
+    }
+
+    impl FakeKernel {
+        pub(crate) fn spawn(states: Vec<PathBuf>) -> Self {
+            let done = Arc::new(AtomicBool::new(false));
+            let flag = done.clone();
+            let mut last: Vec<String> =
+                states.iter().map(|p| fs::read_to_string(p).unwrap_or_default().trim().to_string()).collect();
+            let handle = std::thread::spawn(move || {
+                while !flag.load(Ordering::Relaxed) {
+                    for (p, last) in states.iter().zip(last.iter_mut()) {
+                        let recovery = p.with_file_name("recovery");
+                        if fs::read_to_string(&recovery).is_ok_and(|r| r == "recover") {
+                            fs::write(&recovery, "disabled\n").unwrap();
+                            if last == "crashed" || fs::read_to_string(p).is_ok_and(|c| c.trim() == "crashed") {
+                                fs::write(p, "running\n").unwrap();
+                                *last = "running".to_string();
+                            }
+                            continue;
+                        }
+                        // empty while a write is between truncate and data
+                        let current = match fs::read_to_string(p) {
+                            Ok(c) if !c.is_empty() => c,
+                            _ => continue,
+                        };
+                        let next = match (current.as_str(), last.as_str()) {
+                            ("start", "offline") => "running",
+                            // firmware finishes booting on the next tick
+                            ("booting\n", _) => "running",
+                            ("stop", "running" | "attached") => "offline",
+                            // rejected: the kernel leaves the state as it was
+                            ("start" | "stop", prev) => prev,
+                            (other, _) => {
+                                *last = other.trim().to_string();
+                                continue;
+                            }
+                        }
+                        .to_string();
+                        fs::write(p, format!("{}\n", next)).unwrap();
+                        *last = next;
+                    }
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            });
+            FakeKernel { done, handle: Some(handle) }
+        }
+    }
+
+    impl Drop for FakeKernel {
+        fn drop(&mut self) {
+            self.done.store(true, Ordering::Relaxed);
+            if let Some(h) = self.handle.take() {
+                let _ = h.join();
+            }
+        }
+    }
+
+    pub(crate) fn fake_tree() -> (tempfile::TempDir, SysRoot) {
+        let dir = tempfile::tempdir().unwrap();
+        let rp = dir.path().join("sys/class/remoteproc/remoteproc1");
+        fs::create_dir_all(&rp).unwrap();
+        fs::write(rp.join("state"), "offline\n").unwrap();
+        fs::write(rp.join("firmware"), "am335x-pru0-fw\n").unwrap();
+        let root = SysRoot::new(dir.path());
+        (dir, root)
+    }
+
+    #[test]
+    fn list_and_open_in_fake_root() {
+        let (_dir, root) = fake_tree();
+        assert_eq!(RemoteProc::list_in(&root).unwrap(), vec!["remoteproc1".to_string()]);
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Offline);
+        assert!(RemoteProc::open_in(&root, "remoteproc9").is_err());
+    }
+
+    #[test]
+    fn start_stop_writes_state() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        rp.set_firmware("my-fw.out").unwrap();
+        assert_eq!(fs::read_to_string(rp.path().join("firmware")).unwrap(), "my-fw.out");
+        rp.start().unwrap();
+        assert_eq!(fs::read_to_string(rp.path().join("state")).unwrap(), "start");
+        rp.stop().unwrap();
+        assert_eq!(fs::read_to_string(rp.path().join("state")).unwrap(), "stop");
+    }
+
+    fn add_pru(dir: &Path, instance: &str, name: &str) {
+        let rp = dir.join("sys/class/remoteproc").join(instance);
+        let dev = dir.join("sys/devices/platform/ocp").join(name);
+        fs::create_dir_all(dev.join("of_node")).unwrap();
+        fs::create_dir_all(&rp).unwrap();
+        fs::write(rp.join("name"), format!("{}\n", name)).unwrap();
+        fs::write(rp.join("state"), "offline\n").unwrap();
+        fs::write(rp.join("firmware"), "am335x-pru-fw\n").unwrap();
+        fs::write(rp.join("recovery"), "enabled\n").unwrap();
+        std::os::unix::fs::symlink(&dev, rp.join("device")).unwrap();
+    }
+
+    #[test]
+    fn list_info_and_find_pru() {
+        let dir = tempfile::tempdir().unwrap();
+        add_pru(dir.path(), "remoteproc1", "4a334000.pru");
+        add_pru(dir.path(), "remoteproc2", "4a338000.pru");
+        let root = SysRoot::new(dir.path());
+
+        let infos = RemoteProc::list_info_in(&root).unwrap();
+        assert_eq!(infos.len(), 2);
+        let info = &infos[1];
+        assert_eq!(info.instance, "remoteproc2");
+        assert_eq!(info.name, "4a338000.pru");
+        assert_eq!(info.state, RemoteProcState::Offline);
+        assert_eq!(info.firmware, "am335x-pru-fw");
+        assert_eq!(info.recovery, Some(Recovery::Enabled));
+        assert_eq!(info.coredump, None);
+        assert!(info.device.as_ref().unwrap().ends_with("ocp/4a338000.pru"));
+        assert!(info.of_node.as_ref().unwrap().ends_with("4a338000.pru/of_node"));
+
+        assert_eq!(RemoteProc::find_pru_in(&root, 0, 1).unwrap().instance(), "remoteproc2");
+        assert_eq!(RemoteProc::find_pru_in(&root, 0, 0).unwrap().instance(), "remoteproc1");
+        assert!(RemoteProc::find_pru_in(&root, 1, 0).is_err());
+    }
+
+    #[test]
+    fn deploy_and_start_installs_and_starts() {
+        let (dir, root) = fake_tree();
+        fs::create_dir_all(dir.path().join("lib/firmware")).unwrap();
+        let store = FirmwareStore::system_in(&root);
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+
+        let file_name = rp.deploy_and_start(&store, "pru0-fw", b"\x7fELF").unwrap();
+        assert_eq!(fs::read(store.dir().join(&file_name)).unwrap(), b"\x7fELF");
+        assert_eq!(rp.firmware().unwrap(), "pru0-fw");
+        assert_eq!(store.current("pru0-fw").unwrap(), Some(file_name));
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Online);
+
+        // the next deploy stops the running core first
+        let second = rp.deploy_and_start(&store, "pru0-fw", b"\x7fELF v2").unwrap();
+        assert_eq!(store.current("pru0-fw").unwrap(), Some(second));
+        assert_eq!(rp.state().unwrap(), RemoteProcState::Online);
+    }
+
+    #[test]
+    fn trace_reads_debugfs() {
+        let (dir, root) = fake_tree();
+        let dbg = dir.path().join("sys/kernel/debug/remoteproc/remoteproc1");
+        fs::create_dir_all(&dbg).unwrap();
+        fs::write(dbg.join("trace0"), b"hello from pru\n\0\0\0").unwrap();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        assert_eq!(rp.trace().unwrap(), "hello from pru\n");
+    }
+
+    #[test]
+    fn recovery_and_coredump_attrs() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        assert!(rp.recovery().is_err());
+        fs::write(rp.path().join("recovery"), "enabled\n").unwrap();
+        fs::write(rp.path().join("coredump"), "default\n").unwrap();
+        assert_eq!(rp.recovery().unwrap(), Recovery::Enabled);
+        rp.set_recovery(Recovery::Disabled).unwrap();
+        assert_eq!(rp.recovery().unwrap(), Recovery::Disabled);
+        rp.set_coredump(CoredumpMode::Inline).unwrap();
+        assert_eq!(rp.coredump().unwrap(), CoredumpMode::Inline);
+        fs::write(rp.path().join("coredump"), "bogus\n").unwrap();
+        assert!(matches!(rp.coredump(), Err(RemoteProcError::UnexpectedValue { .. })));
+    }
+
+    #[test]
+    fn parse_kernel_states() {
+        assert_eq!(RemoteProcState::from_str("running\n"), RemoteProcState::Online);
+        assert_eq!(RemoteProcState::from_str("crashed"), RemoteProcState::Crashed);
+        assert_eq!(RemoteProcState::from_str("attached"), RemoteProcState::Attached);
+        assert_eq!(RemoteProcState::from_str("detached"), RemoteProcState::Detached);
+        assert_eq!(RemoteProcState::from_str("deleted"), RemoteProcState::Deleted);
+        assert_eq!(RemoteProcState::from_str("invalid"), RemoteProcState::Invalid);
+        assert_eq!(RemoteProcState::from_str("odd"), RemoteProcState::Unknown("odd".into()));
+    }
+
+    #[test]
+    fn transition_checks_current_state() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        rp.transition(RemoteProcState::Online).unwrap();
+        assert_eq!(fs::read_to_string(rp.path().join("state")).unwrap(), "start");
+
+        fs::write(rp.path().join("state"), "offline\n").unwrap();
+        match rp.transition(RemoteProcState::Detached) {
+            Err(RemoteProcError::InvalidState { current, target }) => {
+                assert_eq!(current, RemoteProcState::Offline);
+                assert_eq!(target, RemoteProcState::Detached);
+            }
+            other => panic!("expected InvalidState, got {:?}", other),
+        }
+
+        // already there: no write
+        rp.transition(RemoteProcState::Offline).unwrap();
+        assert_eq!(fs::read_to_string(rp.path().join("state")).unwrap(), "offline\n");
+    }
+
+    #[test]
+    fn crashed_core_is_recovered_not_stopped() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        fs::write(rp.path().join("state"), "crashed\n").unwrap();
+        fs::write(rp.path().join("recovery"), "disabled\n").unwrap();
+        assert!(matches!(
+            rp.transition(RemoteProcState::Offline),
+            Err(RemoteProcError::InvalidState { current: RemoteProcState::Crashed, .. })
+        ));
+        rp.transition(RemoteProcState::Online).unwrap();
+        assert_eq!(fs::read_to_string(rp.path().join("recovery")).unwrap(), "recover");
+        assert_eq!(fs::read_to_string(rp.path().join("state")).unwrap(), "crashed\n");
+    }
+
+    #[test]
+    fn wait_for_state_sees_change() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        let state = rp.path().join("state");
+        let writer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            fs::write(state, "running\n").unwrap();
+        });
+        rp.wait_for_state(RemoteProcState::Online, Duration::from_secs(5)).unwrap();
+        writer.join().unwrap();
+    }
+
+    #[test]
+    fn wait_for_state_times_out_with_last_state() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        match rp.wait_for_state(RemoteProcState::Online, Duration::from_millis(20)) {
+            Err(RemoteProcError::Timeout { target, last }) => {
+                assert_eq!(target, RemoteProcState::Online);
+                assert_eq!(last, RemoteProcState::Offline);
+            }
+            other => panic!("expected Timeout, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn wait_for_state_accepts_huge_timeout() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        rp.wait_for_state(RemoteProcState::Offline, Duration::MAX).unwrap();
+    }
+
+    #[test]
+    fn errno_maps_to_rejection() {
+        assert_eq!(WriteRejection::from_errno(libc::EBUSY), Some(WriteRejection::Busy));
+        assert_eq!(WriteRejection::from_errno(libc::EINVAL), Some(WriteRejection::Invalid));
+        assert_eq!(WriteRejection::from_errno(libc::EPERM), Some(WriteRejection::NotPermitted));
+        assert_eq!(WriteRejection::from_errno(libc::ENOENT), None);
+    }
+}
@@ -7,12 +7,26 @@ use std::time::Duration;
 use libc;
 use thiserror::Error;
 
+use crate::cores::CoreId;
+use crate::lock::{CoreLock, LockError, LockMode};
+use crate::remoteproc::{RemoteProc, RemoteProcError};
+use crate::sysroot::SysRoot;
+
+const DEV: &str = "/dev";
+/// Directory holding one subdirectory with a `uevent` file per core, for
+/// drivers that expose the remoteproc uevent interface.
+const DEV_REMOTEPROC: &str = "/dev/remoteproc";
+
 #[derive(Debug, Error)]
 pub enum RpmsgError {
     #[error("io: {0}")]
     Io(#[from] io::Error),
     #[error("no rpmsg devices found")]
     NotFound,
+    #[error("lock: {0}")]
+    Lock(#[from] LockError),
+    #[error("remoteproc: {0}")]
+    RemoteProc(#[from] RemoteProcError),
 }
 
 #[derive(Debug)]
@@ -21,13 +35,23 @@ pub struct Rpmsg {
     file: File,
     /// true when the underlying file is an /dev/remoteproc uevent file (read-only lines)
     is_uevent: bool,
+    /// core lock held for the lifetime of the device (see `open_exclusive`)
+    lock: Option<CoreLock>,
 }
 
 impl Rpmsg {
+    /// Number of vrings (one per direction) the firmware's rpmsg vdev must declare.
+    pub const VRING_COUNT: usize = 2;
+
     /// List candidate rpmsg device names from /dev (e.g. "rpmsg0", "rpmsg_pru0").
     pub fn list() -> Result<Vec<String>> {
+        Self::list_in(&SysRoot::host())
+    }
+
+    /// List candidate rpmsg device names from `/dev` under `root` (see `SysRoot`).
+    pub fn list_in(root: &SysRoot) -> Result<Vec<String>> {
         let mut out = Vec::new();
-        for entry in fs::read_dir(Path::new("/dev"))? {
+        for entry in fs::read_dir(root.resolve(DEV))? {
             let e = entry?;
             if let Some(name) = e.file_name().to_str() {
                 if name.starts_with("rpmsg") {
@@ -40,51 +64,117 @@ impl Rpmsg {
 
     /// Open an rpmsg device by its /dev name (e.g. "rpmsg_pru0").
     pub fn open(name: &str) -> Result<Self> {
-        let path = Path::new("/dev").join(name);
+        Self::open_in(&SysRoot::host(), name)
+    }
+
+    /// Open an rpmsg device by its `/dev` name under `root` (see `SysRoot`).
+    pub fn open_in(root: &SysRoot, name: &str) -> Result<Self> {
+        let path = root.resolve(DEV).join(name);
         let file = File::options().read(true).write(true).open(&path)?;
-        Ok(Rpmsg { path, file, is_uevent: false })
+        Ok(Rpmsg { path, file, is_uevent: false, lock: None })
     }
 
-    /// Paths to remoteproc-style uevent devices which drivers may expose.
-    /// Try both `pruss-core0` and `pruss-core1`.
-    const UEVENT_PATHS: [&'static str; 2] = [
-        "/dev/remoteproc/pruss-core0/uevent",
-        "/dev/remoteproc/pruss-core1/uevent",
-    ];
+    /// Open rpmsg device `name` (under the core's `SysRoot`) while holding
+    /// `rproc`'s exclusive core lock. The lock is released when the returned
+    /// `Rpmsg` is dropped.
+    pub fn open_exclusive(rproc: &RemoteProc, name: &str, timeout: Option<Duration>) -> Result<Self> {
+        let lock = rproc.lock(LockMode::Exclusive, timeout)?;
+        let mut rp = Self::open_in(rproc.sysroot(), name)?;
+        rp.lock = Some(lock);
+        Ok(rp)
+    }
 
-    /// Open the first available messaging interface. Prefer the remoteproc uevent
-    /// path if present, otherwise fall back to `/dev/rpmsg*` devices.
-    pub fn open_first() -> Result<Self> {
-        for p in Self::UEVENT_PATHS.iter() {
-            let uevent = Path::new(p);
+    /// The `uevent` files under `/dev/remoteproc/*/` (resolved under `root`),
+    /// sorted by directory name. Empty when the driver exposes none.
+    fn uevent_paths(root: &SysRoot) -> Result<Vec<PathBuf>> {
+        let entries = match fs::read_dir(root.resolve(DEV_REMOTEPROC)) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut out = Vec::new();
+        for entry in entries {
+            let uevent = entry?.path().join("uevent");
             if uevent.exists() {
-                let file = File::options().read(true).open(uevent)?;
-                return Ok(Rpmsg { path: uevent.to_path_buf(), file, is_uevent: true });
+                out.push(uevent);
             }
         }
+        out.sort();
+        Ok(out)
+    }
+
+    /// Open the first available messaging interface. Prefer a remoteproc uevent
+    /// file under `/dev/remoteproc` if present, otherwise fall back to `/dev/rpmsg*` devices.
+    pub fn open_first() -> Result<Self> {
+        Self::open_first_in(&SysRoot::host())
+    }
+
+    /// Same as `open_first`, resolving every path under `root` (see `SysRoot`).
+    pub fn open_first_in(root: &SysRoot) -> Result<Self> {
+        if let Some(uevent) = Self::uevent_paths(root)?.into_iter().next() {
+            let file = File::options().read(true).open(&uevent)?;
+            return Ok(Rpmsg { path: uevent, file, is_uevent: true, lock: None });
+        }
 
-        let list = Self::list()?;
-        let name = list.get(0).ok_or(RpmsgError::NotFound)?;
-        Self::open(name)
+        let list = Self::list_in(root)?;
+        let name = list.first().ok_or(RpmsgError::NotFound)?;
+        Self::open_in(root, name)
     }
 
-    /// Open a specific remoteproc core's uevent interface if present.
-    ///
-    /// `core` selects an index into the built-in `UEVENT_PATHS` array
-    /// (0 => `pruss-core0`, 1 => `pruss-core1`). Returns `NotFound` when the
-    /// requested core path does not exist.
+    /// Open the rpmsg device of PRU `core` of the first ICSS.
+    /// Shorthand for `open_core_id(CoreId::pru(0, core))`.
     pub fn open_core(core: usize) -> Result<Self> {
-        match Self::UEVENT_PATHS.get(core) {
-            Some(p) => {
-                let uevent = Path::new(p);
-                if uevent.exists() {
-                    let file = File::options().read(true).open(uevent)?;
-                    return Ok(Rpmsg { path: uevent.to_path_buf(), file, is_uevent: true });
+        Self::open_core_in(&SysRoot::host(), core)
+    }
+
+    /// Same as `open_core`, resolving every path under `root` (see `SysRoot`).
+    pub fn open_core_in(root: &SysRoot, core: usize) -> Result<Self> {
+        Self::open_core_id_in(root, CoreId::pru(0, core))
+    }
+
+    /// Names of the rpmsg devices in `/dev` that belong to `rproc`, found by
+    /// walking its sysfs device tree (vdev -> virtio -> rpmsg channel).
+    pub fn devices_for(rproc: &RemoteProc) -> Result<Vec<String>> {
+        let dev = rproc.sysroot().resolve(DEV);
+        let mut out = Vec::new();
+        let mut stack = vec![(fs::canonicalize(rproc.path())?, 0)];
+        while let Some((dir, depth)) = stack.pop() {
+            for entry in fs::read_dir(&dir)? {
+                let e = entry?;
+                // only descend into real directories; sysfs links point back up the tree
+                if !e.file_type()?.is_dir() {
+                    continue;
+                }
+                let name = e.file_name().to_string_lossy().into_owned();
+                if name.starts_with("rpmsg") && dev.join(&name).exists() && !out.contains(&name) {
+                    out.push(name);
+                } else if depth < 6 {
+                    stack.push((e.path(), depth + 1));
                 }
-                Err(RpmsgError::NotFound)
             }
-            None => Err(RpmsgError::NotFound),
         }
+        out.sort();
+        Ok(out)
+    }
+
+    /// Open the rpmsg device of core `id` (see `cores::CoreId`).
+    pub fn open_core_id(id: CoreId) -> Result<Self> {
+        Self::open_core_id_in(&SysRoot::host(), id)
+    }
+
+    /// Same as `open_core_id`, resolving every path under `root` (see `SysRoot`).
+    pub fn open_core_id_in(root: &SysRoot, id: CoreId) -> Result<Self> {
+        let rproc = Self::core_rproc(root, id)?;
+        let name = Self::devices_for(&rproc)?.into_iter().next().ok_or(RpmsgError::NotFound)?;
+        Self::open_in(root, &name)
+    }
+
+    /// `RemoteProc::open_core_in`, reporting a missing core as `NotFound`.
+    fn core_rproc(root: &SysRoot, id: CoreId) -> Result<RemoteProc> {
+        RemoteProc::open_core_in(root, id).map_err(|e| match e {
+            RemoteProcError::Io(e) if e.kind() == io::ErrorKind::NotFound => RpmsgError::NotFound,
+            e => RpmsgError::RemoteProc(e),
+        })
     }
 
     /// Open a uevent path by arbitrary filesystem path (e.g. "/dev/remoteproc/pruss-core1/uevent").
@@ -92,7 +182,7 @@ impl Rpmsg {
         let p = path.as_ref();
         if p.exists() {
             let file = File::options().read(true).open(p)?;
-            return Ok(Rpmsg { path: p.to_path_buf(), file, is_uevent: true });
+            return Ok(Rpmsg { path: p.to_path_buf(), file, is_uevent: true, lock: None });
         }
         Err(RpmsgError::NotFound)
     }
@@ -156,10 +246,7 @@ impl Rpmsg {
     /// Send bytes to the PRU over rpmsg.
     pub fn send(&mut self, data: &[u8]) -> Result<usize> {
         if self.is_uevent {
-            return Err(RpmsgError::Io(io::Error::new(
-                io::ErrorKind::Other,
-                "send not supported on uevent",
-            )));
+            return Err(RpmsgError::Io(io::Error::other("send not supported on uevent")));
         }
         let n = self.file.write(data)?;
         Ok(n)
@@ -174,8 +261,15 @@ impl Rpmsg {
     pub fn is_uevent(&self) -> bool {
         self.is_uevent
     }
+
+    /// Return the core lock held by this device, if opened with `open_exclusive`.
+    pub fn core_lock(&self) -> Option<&CoreLock> {
+        self.lock.as_ref()
+    }
 }
 
+pub type Result<T> = std::result::Result<T, RpmsgError>;
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -202,9 +296,102 @@ mod tests {
             other => panic!("expected NotFound, got {:?}", other),
         }
     }
-}
 
-pub type Result<T> = std::result::Result<T, RpmsgError>;
+    #[test]
+    fn message_flow_in_fake_root() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("dev")).unwrap();
+        fs::write(dir.path().join("dev/rpmsg_pru30"), b"").unwrap();
+        let root = SysRoot::new(dir.path());
+
+        assert_eq!(Rpmsg::list_in(&root).unwrap(), vec!["rpmsg_pru30".to_string()]);
+        let mut rp = Rpmsg::open_first_in(&root).unwrap();
+        assert!(!rp.is_uevent());
+        assert_eq!(rp.send(b"ping").unwrap(), 4);
+
+        let mut rp = Rpmsg::open_in(&root, "rpmsg_pru30").unwrap();
+        assert_eq!(rp.read_message().unwrap(), b"ping");
+    }
+
+    #[test]
+    fn open_exclusive_holds_core_lock() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("sys/class/remoteproc/remoteproc1")).unwrap();
+        fs::create_dir_all(dir.path().join("dev")).unwrap();
+        fs::write(dir.path().join("dev/rpmsg_pru30"), b"").unwrap();
+        let root = SysRoot::new(dir.path());
+        let rproc = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+
+        let rp = Rpmsg::open_exclusive(&rproc, "rpmsg_pru30", Some(Duration::ZERO)).unwrap();
+        assert!(rp.core_lock().is_some());
+        assert!(matches!(
+            Rpmsg::open_exclusive(&rproc, "rpmsg_pru30", Some(Duration::ZERO)),
+            Err(RpmsgError::Lock(LockError::Busy { .. }))
+        ));
+        drop(rp);
+        rproc.lock(LockMode::Exclusive, Some(Duration::ZERO)).unwrap();
+    }
+
+    #[test]
+    fn open_core_id_finds_channel_device() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::cores::tests::add_core(dir.path(), "remoteproc1", "4a300000.pruss", "4a334000.pru");
+        crate::cores::tests::add_core(dir.path(), "remoteproc2", "4a300000.pruss", "4a338000.pru");
+        // rpmsg channel nested under the second core's class device
+        let rproc2 = fs::canonicalize(dir.path().join("sys/class/remoteproc/remoteproc2")).unwrap();
+        fs::create_dir_all(rproc2.join("virtio0/virtio0.rpmsg-pru.-1.31/rpmsg/rpmsg_pru31")).unwrap();
+        fs::create_dir_all(dir.path().join("dev")).unwrap();
+        fs::write(dir.path().join("dev/rpmsg_pru31"), b"").unwrap();
+        let root = SysRoot::new(dir.path());
+
+        let rp = Rpmsg::open_core_id_in(&root, CoreId::pru(0, 1)).unwrap();
+        assert_eq!(rp.path(), dir.path().join("dev/rpmsg_pru31"));
+        assert!(matches!(Rpmsg::open_core_id_in(&root, CoreId::pru(0, 0)), Err(RpmsgError::NotFound)));
+    }
+
+    #[test]
+    fn open_core_in_fake_root() {
+        let dir = tempfile::tempdir().unwrap();
+        crate::cores::tests::add_core(dir.path(), "remoteproc1", "4a300000.pruss", "4a334000.pru");
+        let rproc1 = fs::canonicalize(dir.path().join("sys/class/remoteproc/remoteproc1")).unwrap();
+        fs::create_dir_all(rproc1.join("virtio0/virtio0.rpmsg-pru.-1.30/rpmsg/rpmsg_pru30")).unwrap();
+        fs::create_dir_all(dir.path().join("dev")).unwrap();
+        fs::write(dir.path().join("dev/rpmsg_pru30"), b"").unwrap();
+        let root = SysRoot::new(dir.path());
+
+        assert_eq!(Rpmsg::open_core_in(&root, 0).unwrap().path(), dir.path().join("dev/rpmsg_pru30"));
+        assert!(matches!(Rpmsg::open_core_in(&root, 1), Err(RpmsgError::NotFound)));
+    }
+
+    #[test]
+    fn open_first_prefers_any_uevent() {
+        let dir = tempfile::tempdir().unwrap();
+        let core = dir.path().join("dev/remoteproc/icss1-pru0");
+        fs::create_dir_all(&core).unwrap();
+        fs::write(core.join("uevent"), "EVENT=up\n").unwrap();
+        fs::write(dir.path().join("dev/rpmsg_pru30"), b"").unwrap();
+        let root = SysRoot::new(dir.path());
+
+        let mut rp = Rpmsg::open_first_in(&root).unwrap();
+        assert!(rp.is_uevent());
+        assert_eq!(rp.path(), core.join("uevent"));
+        assert_eq!(rp.read_message().unwrap(), b"EVENT=up\n");
+    }
+
+    #[test]
+    fn open_core_id_passes_other_errors_through() {
+        let dir = tempfile::tempdir().unwrap();
+        // a file where the remoteproc class directory should be
+        fs::create_dir_all(dir.path().join("sys/class")).unwrap();
+        fs::write(dir.path().join("sys/class/remoteproc"), b"").unwrap();
+        let root = SysRoot::new(dir.path());
+
+        assert!(matches!(
+            Rpmsg::open_core_id_in(&root, CoreId::pru(0, 0)),
+            Err(RpmsgError::RemoteProc(RemoteProcError::Io(_)))
+        ));
+    }
+}
 
 #[cfg(feature = "async")]
 pub mod async_impl {
@@ -225,23 +412,25 @@ pub mod async_impl {
     impl AsyncRpmsg {
         /// Open the first available messaging interface with O_NONBLOCK.
         pub async fn open_first() -> Result<Self> {
+            Self::open_first_in(&SysRoot::host()).await
+        }
+
+        /// Same as `open_first`, resolving every path under `root` (see `SysRoot`).
+        pub async fn open_first_in(root: &SysRoot) -> Result<Self> {
             // prefer uevent if present
-            for p in Rpmsg::UEVENT_PATHS.iter() {
-                let uevent = Path::new(p);
-                if uevent.exists() {
-                    let file = OpenOptions::new()
-                        .read(true)
-                        .custom_flags(libc::O_NONBLOCK)
-                        .open(uevent)?;
-                    let afd = AsyncFd::new(file)?;
-                    return Ok(AsyncRpmsg { fd: afd, path: uevent.to_path_buf(), is_uevent: true });
-                }
+            if let Some(uevent) = Rpmsg::uevent_paths(root)?.into_iter().next() {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .custom_flags(libc::O_NONBLOCK)
+                    .open(&uevent)?;
+                let afd = AsyncFd::new(file)?;
+                return Ok(AsyncRpmsg { fd: afd, path: uevent, is_uevent: true });
             }
 
             // fallback to first /dev/rpmsg* device (read/write)
-            let list = Rpmsg::list()?;
-            let name = list.get(0).ok_or(RpmsgError::NotFound)?;
-            let path = Path::new("/dev").join(name);
+            let list = Rpmsg::list_in(root)?;
+            let name = list.first().ok_or(RpmsgError::NotFound)?;
+            let path = root.resolve(DEV).join(name);
             let file = OpenOptions::new()
                 .read(true)
                 .write(true)
@@ -267,11 +456,11 @@ pub mod async_impl {
             let mut guard: AsyncFdReadyGuard<'_, File> = if let Some(dur) = timeout {
                 match tokio::time::timeout(dur, ready_fut).await {
                     Ok(Ok(g)) => g,
-                    Ok(Err(e)) => return Err(RpmsgError::Io(io::Error::new(io::ErrorKind::Other, e.to_string()))),
+                    Ok(Err(e)) => return Err(RpmsgError::Io(io::Error::other(e.to_string()))),
                     Err(_) => return Ok(None),
                 }
             } else {
-                ready_fut.await.map_err(|e| RpmsgError::Io(io::Error::new(io::ErrorKind::Other, e.to_string())))?
+                ready_fut.await.map_err(|e| RpmsgError::Io(io::Error::other(e.to_string())))?
             };
 
             // Try a non-blocking read. If it would block, clear readiness and wait again.
@@ -293,12 +482,12 @@ pub mod async_impl {
                         return Ok(Some(Vec::new()));
                     }
                     buf.truncate(n);
-                    return Ok(Some(buf));
+                    Ok(Some(buf))
                 }
-                Ok(Err(e)) => return Err(RpmsgError::Io(e)),
+                Ok(Err(e)) => Err(RpmsgError::Io(e)),
                 Err(_would_block) => {
                     // try_io indicated the fd would block; return None to indicate no data
-                    return Ok(None);
+                    Ok(None)
                 }
             }
         }
@@ -306,11 +495,11 @@ pub mod async_impl {
         /// Send data to PRU. Not supported for uevent-backed interfaces.
         pub async fn send(&self, data: &[u8]) -> Result<usize> {
             if self.is_uevent {
-                return Err(RpmsgError::Io(io::Error::new(io::ErrorKind::Other, "send not supported on uevent")));
+                return Err(RpmsgError::Io(io::Error::other("send not supported on uevent")));
             }
 
             // Wait for writable readiness
-            let mut guard: AsyncFdReadyGuard<'_, File> = self.fd.writable().await.map_err(|e| RpmsgError::Io(io::Error::new(io::ErrorKind::Other, e.to_string())))?;
+            let mut guard: AsyncFdReadyGuard<'_, File> = self.fd.writable().await.map_err(|e| RpmsgError::Io(io::Error::other(e.to_string())))?;
             let res = guard.try_io(|inner: &AsyncFd<File>| {
                 let fd = inner.get_ref().as_raw_fd();
                 let w = unsafe { libc::write(fd, data.as_ptr() as *const libc::c_void, data.len()) };
@@ -335,21 +524,34 @@ pub mod async_impl {
             self.is_uevent
         }
         
-        /// Open a specific core index asynchronously (0 => core0, 1 => core1).
+        /// Open the rpmsg device of PRU `core` of the first ICSS asynchronously.
+        /// Shorthand for `open_core_id(CoreId::pru(0, core))`.
         pub async fn open_core(core: usize) -> Result<Self> {
-            if let Some(p) = Rpmsg::UEVENT_PATHS.get(core) {
-                let uevent = Path::new(p);
-                if uevent.exists() {
-                    let file = OpenOptions::new()
-                        .read(true)
-                        .custom_flags(libc::O_NONBLOCK)
-                        .open(uevent)?;
-                    let afd = AsyncFd::new(file)?;
-                    return Ok(AsyncRpmsg { fd: afd, path: uevent.to_path_buf(), is_uevent: true });
-                }
-                return Err(RpmsgError::NotFound);
-            }
-            Err(RpmsgError::NotFound)
+            Self::open_core_in(&SysRoot::host(), core).await
+        }
+
+        /// Same as `open_core`, resolving every path under `root` (see `SysRoot`).
+        pub async fn open_core_in(root: &SysRoot, core: usize) -> Result<Self> {
+            Self::open_core_id_in(root, CoreId::pru(0, core)).await
+        }
+
+        /// Open the rpmsg device of core `id` asynchronously (see `cores::CoreId`).
+        pub async fn open_core_id(id: CoreId) -> Result<Self> {
+            Self::open_core_id_in(&SysRoot::host(), id).await
+        }
+
+        /// Same as `open_core_id`, resolving every path under `root` (see `SysRoot`).
+        pub async fn open_core_id_in(root: &SysRoot, id: CoreId) -> Result<Self> {
+            let rproc = Rpmsg::core_rproc(root, id)?;
+            let name = Rpmsg::devices_for(&rproc)?.into_iter().next().ok_or(RpmsgError::NotFound)?;
+            let path = root.resolve(DEV).join(name);
+            let file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(&path)?;
+            let afd = AsyncFd::new(file)?;
+            Ok(AsyncRpmsg { fd: afd, path, is_uevent: false })
         }
 
         /// Open a uevent path by arbitrary filesystem path asynchronously.
@@ -0,0 +1,442 @@
+use std::collections::VecDeque;
+use std::ops::ControlFlow;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::remoteproc::{RemoteProc, RemoteProcError, RemoteProcState};
+use crate::rpmsg::{Rpmsg, RpmsgError};
+
+#[derive(Debug, Error)]
+pub enum SupervisorError {
+    #[error("remoteproc: {0}")]
+    RemoteProc(#[from] RemoteProcError),
+    #[error("rpmsg: {0}")]
+    Rpmsg(#[from] RpmsgError),
+    #[error("gave up after {0} restart attempts")]
+    RetriesExhausted(u32),
+    #[error("crash loop: {restarts} restarts within {window:?}")]
+    CrashLoop { restarts: usize, window: Duration },
+    #[error("a heartbeat needs a channel to arrive on")]
+    HeartbeatWithoutChannel,
+}
+
+/// When and how often `Supervisor` restarts a failed core.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RestartPolicy {
+    /// Consecutive restart attempts before giving up (`None`: never give up).
+    pub max_retries: Option<u32>,
+    /// Delay before the first restart; doubled for each further attempt.
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// The attempt counter resets once the core has stayed up this long.
+    pub reset_after: Duration,
+    /// Give up when this many restarts happen within the window.
+    pub crash_loop: Option<(usize, Duration)>,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        RestartPolicy {
+            max_retries: Some(5),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            reset_after: Duration::from_secs(60),
+            crash_loop: Some((10, Duration::from_secs(300))),
+        }
+    }
+}
+
+impl RestartPolicy {
+    /// Backoff before restart attempt `attempt` (1-based).
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt.saturating_sub(1)).unwrap_or(u32::MAX);
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Why the supervisor considers the core failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// The core left `Online` (e.g. `crashed`, or stopped behind our back).
+    State(RemoteProcState),
+    /// No heartbeat message within the configured timeout.
+    HeartbeatLost,
+    /// The channel could not be opened or read, e.g. because its rpmsg
+    /// device went away with the core.
+    Channel(String),
+}
+
+/// Reported to the `Supervisor::run` callback.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SupervisorEvent {
+    /// A message arrived on the channel that is not a heartbeat.
+    Message(Vec<u8>),
+    Failed(Failure),
+    /// About to restart after `delay`.
+    Restarting { attempt: u32, delay: Duration },
+    /// The core is `Online` again and the channel was reopened.
+    Restarted { attempt: u32 },
+    /// A restart attempt failed; another will follow if the policy allows.
+    RestartFailed { attempt: u32, error: String },
+}
+
+type HeartbeatMatch = Box<dyn Fn(&[u8]) -> bool + Send>;
+
+struct Heartbeat {
+    timeout: Duration,
+    is_heartbeat: HeartbeatMatch,
+}
+
+type OpenChannel = Box<dyn FnMut() -> crate::rpmsg::Result<Rpmsg> + Send>;
+
+/// Keeps a PRU core running: watches its state (and optionally an
+/// application heartbeat on its rpmsg channel) and restarts it according
+/// to a `RestartPolicy`, reopening the channel after every restart.
+pub struct Supervisor {
+    rproc: RemoteProc,
+    policy: RestartPolicy,
+    open_channel: Option<OpenChannel>,
+    heartbeat: Option<Heartbeat>,
+    interval: Duration,
+    start_timeout: Duration,
+}
+
+impl Supervisor {
+    pub fn new(rproc: RemoteProc) -> Self {
+        Supervisor {
+            rproc,
+            policy: RestartPolicy::default(),
+            open_channel: None,
+            heartbeat: None,
+            interval: Duration::from_millis(100),
+            start_timeout: Duration::from_secs(5),
+        }
+    }
+
+    pub fn policy(mut self, policy: RestartPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Open a messaging channel with `open` now and after every restart.
+    pub fn channel<F>(mut self, open: F) -> Self
+    where
+        F: FnMut() -> crate::rpmsg::Result<Rpmsg> + Send + 'static,
+    {
+        self.open_channel = Some(Box::new(open));
+        self
+    }
+
+    /// Treat the core as failed when no message matching `is_heartbeat`
+    /// arrives within `timeout`. Requires a `channel`; without one `run`
+    /// fails with `HeartbeatWithoutChannel`.
+    pub fn heartbeat<F>(mut self, timeout: Duration, is_heartbeat: F) -> Self
+    where
+        F: Fn(&[u8]) -> bool + Send + 'static,
+    {
+        self.heartbeat = Some(Heartbeat { timeout, is_heartbeat: Box::new(is_heartbeat) });
+        self
+    }
+
+    /// How often state is checked (default 100 ms).
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    /// How long a restart may take to reach `Online` (default 5 s).
+    pub fn start_timeout(mut self, timeout: Duration) -> Self {
+        self.start_timeout = timeout;
+        self
+    }
+
+    pub fn remoteproc(&self) -> &RemoteProc {
+        &self.rproc
+    }
+
+    /// Supervise until `on_event` returns `ControlFlow::Break` or the policy
+    /// gives up. The callback also gets the current channel, if any, so it
+    /// can reply to messages.
+    pub fn run<F>(&mut self, mut on_event: F) -> Result<()>
+    where
+        F: FnMut(&SupervisorEvent, Option<&mut Rpmsg>) -> ControlFlow<()>,
+    {
+        if self.heartbeat.is_some() && self.open_channel.is_none() {
+            return Err(SupervisorError::HeartbeatWithoutChannel);
+        }
+        let (mut channel, mut pending) = match self.open() {
+            Ok(ch) => (ch, None),
+            Err(e) => (None, Some(Failure::Channel(e.to_string()))),
+        };
+        let mut attempt = 0u32;
+        let mut up_since = Instant::now();
+        let mut last_heartbeat = Instant::now();
+        let mut restarts: VecDeque<Instant> = VecDeque::new();
+
+        macro_rules! emit {
+            ($ev:expr) => {
+                if on_event(&$ev, channel.as_mut()).is_break() {
+                    return Ok(());
+                }
+            };
+        }
+
+        loop {
+            let polled = match pending.take() {
+                Some(f) => Poll::Failed(f),
+                None => self.poll(&mut channel, &mut last_heartbeat)?,
+            };
+            let failure = match polled {
+                Poll::Healthy(messages) => {
+                    for m in messages {
+                        emit!(SupervisorEvent::Message(m));
+                    }
+                    if attempt > 0 && up_since.elapsed() >= self.policy.reset_after {
+                        attempt = 0;
+                    }
+                    continue;
+                }
+                Poll::Failed(f) => f,
+            };
+            channel = None;
+            emit!(SupervisorEvent::Failed(failure));
+
+            // restart until it sticks or the policy gives up
+            let mut core_up = false;
+            loop {
+                attempt += 1;
+                if self.policy.max_retries.is_some_and(|max| attempt > max) {
+                    return Err(SupervisorError::RetriesExhausted(attempt - 1));
+                }
+                let now = Instant::now();
+                restarts.push_back(now);
+                if let Some((limit, window)) = self.policy.crash_loop {
+                    while restarts.front().is_some_and(|t| now.duration_since(*t) > window) {
+                        restarts.pop_front();
+                    }
+                    if restarts.len() > limit {
+                        return Err(SupervisorError::CrashLoop { restarts: restarts.len(), window });
+                    }
+                }
+
+                let delay = self.policy.backoff(attempt);
+                emit!(SupervisorEvent::Restarting { attempt, delay });
+                thread::sleep(delay);
+                match self.restart(&mut core_up) {
+                    Ok(ch) => {
+                        channel = ch;
+                        up_since = Instant::now();
+                        last_heartbeat = up_since;
+                        emit!(SupervisorEvent::Restarted { attempt });
+                        break;
+                    }
+                    Err(e) => emit!(SupervisorEvent::RestartFailed { attempt, error: e.to_string() }),
+                }
+            }
+        }
+    }
+
+    fn open(&mut self) -> Result<Option<Rpmsg>> {
+        match self.open_channel.as_mut() {
+            Some(open) => Ok(Some(open()?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Bring the core back and reopen the channel. `core_up` records that an
+    /// earlier attempt already restarted the core, so a channel that was slow
+    /// to reappear does not cost another restart while the core stays `Online`.
+    fn restart(&mut self, core_up: &mut bool) -> Result<Option<Rpmsg>> {
+        if !(*core_up && self.rproc.state()? == RemoteProcState::Online) {
+            *core_up = false;
+            self.restart_core()?;
+            *core_up = true;
+        }
+        self.reopen()
+    }
+
+    fn restart_core(&self) -> Result<()> {
+        match self.rproc.state()? {
+            RemoteProcState::Offline => {}
+            // the kernel refuses `stop` on a crashed core; `recover` restarts it
+            RemoteProcState::Crashed => {
+                self.rproc.recover()?;
+                self.rproc.wait_for_state(RemoteProcState::Online, self.start_timeout)?;
+                return Ok(());
+            }
+            _ => self.rproc.stop_and_wait(self.start_timeout)?,
+        }
+        self.rproc.start_and_wait(self.start_timeout)?;
+        Ok(())
+    }
+
+    /// Open the channel, retrying every interval for up to `start_timeout`
+    /// while the rpmsg device is being recreated.
+    fn reopen(&mut self) -> Result<Option<Rpmsg>> {
+        let deadline = Instant::now().checked_add(self.start_timeout);
+        loop {
+            match self.open() {
+                Err(_) if deadline.is_none_or(|d| Instant::now() < d) => thread::sleep(self.interval),
+                other => return other,
+            }
+        }
+    }
+
+    /// Wait one interval for messages and check the core's health.
+    fn poll(&self, channel: &mut Option<Rpmsg>, last_heartbeat: &mut Instant) -> Result<Poll> {
+        let mut messages = Vec::new();
+        let mut channel_error = None;
+        match channel {
+            Some(ch) => match ch.read_message_timeout(Some(self.interval)) {
+                Ok(Some(msg)) => match &self.heartbeat {
+                    Some(hb) if (hb.is_heartbeat)(&msg) => *last_heartbeat = Instant::now(),
+                    _ if msg.is_empty() => {}
+                    _ => messages.push(msg),
+                },
+                Ok(None) => {}
+                Err(e) => channel_error = Some(e.to_string()),
+            },
+            None => thread::sleep(self.interval),
+        }
+
+        // a crash also takes the channel down; report the crash
+        let state = self.rproc.state()?;
+        if state != RemoteProcState::Online {
+            return Ok(Poll::Failed(Failure::State(state)));
+        }
+        if let Some(e) = channel_error {
+            return Ok(Poll::Failed(Failure::Channel(e)));
+        }
+        if let Some(hb) = &self.heartbeat {
+            if last_heartbeat.elapsed() > hb.timeout {
+                return Ok(Poll::Failed(Failure::HeartbeatLost));
+            }
+        }
+        Ok(Poll::Healthy(messages))
+    }
+}
+
+enum Poll {
+    Healthy(Vec<Vec<u8>>),
+    Failed(Failure),
+}
+
+pub type Result<T> = std::result::Result<T, SupervisorError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::remoteproc::tests::{fake_tree, FakeKernel};
+    use std::fs;
+
+    fn quick_policy() -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(4),
+            ..RestartPolicy::default()
+        }
+    }
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        let p = quick_policy();
+        assert_eq!(p.backoff(1), Duration::from_millis(1));
+        assert_eq!(p.backoff(3), Duration::from_millis(4));
+        assert_eq!(p.backoff(40), Duration::from_millis(4));
+    }
+
+    #[test]
+    fn restarts_crashed_core() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        fs::write(rp.path().join("state"), "crashed\n").unwrap();
+        fs::write(rp.path().join("recovery"), "disabled\n").unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+
+        let mut sup = Supervisor::new(rp).policy(quick_policy()).interval(Duration::from_millis(1));
+        let mut events = Vec::new();
+        sup.run(|ev, _| {
+            events.push(ev.clone());
+            match ev {
+                SupervisorEvent::Restarted { .. } => ControlFlow::Break(()),
+                _ => ControlFlow::Continue(()),
+            }
+        })
+        .unwrap();
+
+        assert_eq!(events[0], SupervisorEvent::Failed(Failure::State(RemoteProcState::Crashed)));
+        assert_eq!(events.last(), Some(&SupervisorEvent::Restarted { attempt: 1 }));
+        assert_eq!(sup.remoteproc().state().unwrap(), RemoteProcState::Online);
+    }
+
+    #[test]
+    fn gives_up_after_max_retries() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        fs::write(rp.path().join("state"), "crashed\n").unwrap();
+        fs::write(rp.path().join("recovery"), "disabled\n").unwrap();
+        // no fake kernel: the core never comes back
+        let policy = RestartPolicy { max_retries: Some(2), ..quick_policy() };
+        let mut sup = Supervisor::new(rp)
+            .policy(policy)
+            .interval(Duration::from_millis(1))
+            .start_timeout(Duration::from_millis(5));
+        match sup.run(|_, _| ControlFlow::Continue(())) {
+            Err(SupervisorError::RetriesExhausted(2)) => {}
+            other => panic!("expected RetriesExhausted, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn channel_errors_restart_core_and_channel_is_awaited() {
+        let (dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        fs::write(rp.path().join("state"), "running\n").unwrap();
+        fs::create_dir_all(dir.path().join("dev")).unwrap();
+        let _kernel = FakeKernel::spawn(vec![rp.path().join("state")]);
+
+        // the device is missing at first and takes a few tries to reappear
+        let dev = dir.path().join("dev/rpmsg_pru30");
+        let mut opens = 0;
+        let mut sup = Supervisor::new(rp)
+            .policy(quick_policy())
+            .interval(Duration::from_millis(1))
+            .channel(move || {
+                opens += 1;
+                if opens == 4 {
+                    fs::write(&dev, b"").unwrap();
+                }
+                Rpmsg::open_in(&root, "rpmsg_pru30")
+            });
+        let mut events = Vec::new();
+        sup.run(|ev, ch| {
+            events.push(ev.clone());
+            match ev {
+                SupervisorEvent::Restarted { .. } => {
+                    assert!(ch.is_some());
+                    ControlFlow::Break(())
+                }
+                _ => ControlFlow::Continue(()),
+            }
+        })
+        .unwrap();
+
+        assert!(matches!(&events[0], SupervisorEvent::Failed(Failure::Channel(_))));
+        assert!(matches!(events[1], SupervisorEvent::Restarting { attempt: 1, .. }));
+        assert_eq!(events[2], SupervisorEvent::Restarted { attempt: 1 });
+        assert_eq!(sup.remoteproc().state().unwrap(), RemoteProcState::Online);
+    }
+
+    #[test]
+    fn heartbeat_requires_channel() {
+        let (_dir, root) = fake_tree();
+        let rp = RemoteProc::open_in(&root, "remoteproc1").unwrap();
+        let mut sup = Supervisor::new(rp).heartbeat(Duration::from_millis(10), |m| m == b"hb");
+        assert!(matches!(
+            sup.run(|_, _| ControlFlow::Continue(())),
+            Err(SupervisorError::HeartbeatWithoutChannel)
+        ));
+    }
+}
@@ -0,0 +1,53 @@
+use std::path::{Path, PathBuf};
+
+/// Filesystem root used to resolve kernel interfaces such as `/sys` and `/dev`.
+///
+/// On target this is `/`. Tests and CI can point it at a directory holding a
+/// fake tree (e.g. `<tmp>/sys/class/remoteproc/remoteproc1/state`) and drive
+/// `RemoteProc` and `Rpmsg` against it without real hardware.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SysRoot {
+    root: PathBuf,
+}
+
+impl SysRoot {
+    /// Use `root` as the prefix for every system path.
+    pub fn new<P: AsRef<Path>>(root: P) -> Self {
+        SysRoot { root: root.as_ref().to_path_buf() }
+    }
+
+    /// The real host filesystem (`/`).
+    pub fn host() -> Self {
+        SysRoot::new("/")
+    }
+
+    /// Return the root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolve an absolute system path (e.g. `/sys/class/remoteproc`) under this root.
+    pub fn resolve<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        let p = path.as_ref();
+        let rel = p.strip_prefix("/").unwrap_or(p);
+        self.root.join(rel)
+    }
+}
+
+impl Default for SysRoot {
+    fn default() -> Self {
+        SysRoot::host()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_under_root() {
+        let root = SysRoot::new("/tmp/fake");
+        assert_eq!(root.resolve("/sys/class/remoteproc"), PathBuf::from("/tmp/fake/sys/class/remoteproc"));
+        assert_eq!(SysRoot::host().resolve("/dev"), PathBuf::from("/dev"));
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::remoteproc::Result;
+
+/// Tracks successive snapshots of a remoteproc trace buffer and extracts the
+/// lines written in between.
+///
+/// The firmware writes the buffer circularly and debugfs returns it up to the
+/// first NUL, so once it wraps a snapshot is "new head + old tail". New data
+/// is whatever was appended past the previous snapshot's end followed by the
+/// rewritten prefix: everything up to the last byte that differs from the
+/// previous snapshot, extended to the end of that line. This is best effort
+/// and assumes the firmware writes whole lines between snapshots.
+#[derive(Debug, Default)]
+pub struct TraceTail {
+    prev: Vec<u8>,
+    partial: Vec<u8>,
+}
+
+impl TraceTail {
+    /// Start tracking from `snapshot`; its contents are treated as already seen.
+    pub fn new(snapshot: &[u8]) -> Self {
+        TraceTail { prev: trim_nul(snapshot).to_vec(), partial: Vec::new() }
+    }
+
+    /// Feed a new snapshot and return the complete lines written since the last one.
+    pub fn update(&mut self, snapshot: &[u8]) -> Vec<String> {
+        let cur = trim_nul(snapshot);
+        let mut fresh = Vec::new();
+        if cur.starts_with(&self.prev) {
+            fresh.extend_from_slice(&cur[self.prev.len()..]);
+        } else {
+            let common = cur.len().min(self.prev.len());
+            let mut rewritten = (0..common).rev().find(|&i| cur[i] != self.prev[i]).map_or(0, |i| i + 1);
+            if rewritten > 0 && cur[rewritten - 1] != b'\n' {
+                if let Some(nl) = cur[rewritten..common].iter().position(|b| *b == b'\n') {
+                    rewritten += nl + 1;
+                }
+            }
+            if cur.len() > self.prev.len() {
+                fresh.extend_from_slice(&cur[self.prev.len()..]);
+            }
+            fresh.extend_from_slice(&cur[..rewritten]);
+        }
+        self.prev = cur.to_vec();
+
+        self.partial.extend_from_slice(&fresh);
+        let mut lines = Vec::new();
+        while let Some(pos) = self.partial.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.partial.drain(..=pos).collect();
+            lines.push(String::from_utf8_lossy(&line[..pos]).into_owned());
+        }
+        lines
+    }
+}
+
+fn trim_nul(b: &[u8]) -> &[u8] {
+    let end = b.iter().position(|c| *c == 0).unwrap_or(b.len());
+    &b[..end]
+}
+
+/// Blocking iterator over new trace lines, re-reading the buffer every `interval`.
+pub struct TraceFollow {
+    path: PathBuf,
+    tail: TraceTail,
+    lines: VecDeque<String>,
+    interval: Duration,
+}
+
+impl TraceFollow {
+    pub(crate) fn new(path: PathBuf, interval: Duration) -> Result<Self> {
+        let tail = TraceTail::new(&fs::read(&path)?);
+        Ok(TraceFollow { path, tail, lines: VecDeque::new(), interval })
+    }
+
+    /// Path of the trace buffer being followed.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Re-read the buffer once and return any lines that are now complete,
+    /// without blocking.
+    pub fn poll_lines(&mut self) -> Result<Vec<String>> {
+        let snapshot = read_snapshot(&self.path)?;
+        Ok(self.tail.update(&snapshot))
+    }
+}
+
+impl Iterator for TraceFollow {
+    type Item = Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(line) = self.lines.pop_front() {
+                return Some(Ok(line));
+            }
+            match self.poll_lines() {
+                Ok(lines) if lines.is_empty() => thread::sleep(self.interval),
+                Ok(lines) => self.lines.extend(lines),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+    }
+}
+
+/// Read the trace buffer; a buffer that disappeared (core stopped) reads as empty.
+fn read_snapshot(path: &Path) -> Result<Vec<u8>> {
+    match fs::read(path) {
+        Ok(b) => Ok(b),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_appends() {
+        let mut t = TraceTail::new(b"boot\n");
+        assert!(t.update(b"boot\n").is_empty());
+        assert_eq!(t.update(b"boot\nline 1\nlin"), vec!["line 1".to_string()]);
+        assert_eq!(t.update(b"boot\nline 1\nline 2\n\0\0"), vec!["line 2".to_string()]);
+    }
+
+    #[test]
+    fn tail_handles_wrap() {
+        let mut t = TraceTail::new(b"aaaa\nbbbb\ncccc\n");
+        // firmware wrapped and rewrote the first ten bytes
+        assert_eq!(t.update(b"dddd\neeee\ncccc\n"), vec!["dddd".to_string(), "eeee".to_string()]);
+        // tail past the old end plus a wrapped head
+        let mut t = TraceTail::new(b"aaaa\nbb");
+        assert_eq!(t.update(b"ff\na\nbbb\n"), vec!["b".to_string(), "ff".to_string()]);
+    }
+
+    #[test]
+    fn follow_yields_only_new_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trace0");
+        fs::write(&path, "old\n").unwrap();
+        let mut f = TraceFollow::new(path.clone(), Duration::from_millis(1)).unwrap();
+        fs::write(&path, "old\nnew\n").unwrap();
+        assert_eq!(f.next().unwrap().unwrap(), "new");
+    }
+}
+
+#[cfg(feature = "async")]
+pub mod async_impl {
+    use super::*;
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+
+    use futures_core::Stream;
+
+    /// Async variant of `TraceFollow`: a `Stream` of new trace lines that
+    /// waits with `tokio::time` between reads. The buffer itself is read
+    /// synchronously; debugfs reads do not block. The stream never ends.
+    pub struct AsyncTraceFollow {
+        inner: TraceFollow,
+        sleep: Option<Pin<Box<tokio::time::Sleep>>>,
+    }
+
+    impl AsyncTraceFollow {
+        pub(crate) async fn new(path: PathBuf, interval: Duration) -> Result<Self> {
+            let tail = TraceTail::new(&fs::read(&path)?);
+            Ok(AsyncTraceFollow {
+                inner: TraceFollow { path, tail, lines: VecDeque::new(), interval },
+                sleep: None,
+            })
+        }
+
+        pub fn path(&self) -> &Path {
+            self.inner.path()
+        }
+
+        /// Wait for and return the next new trace line.
+        pub async fn next_line(&mut self) -> Result<String> {
+            let line = std::future::poll_fn(|cx| Pin::new(&mut *self).poll_next(cx)).await;
+            line.expect("trace stream never ends")
+        }
+    }
+
+    impl Stream for AsyncTraceFollow {
+        type Item = Result<String>;
+
+        fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            let this = self.get_mut();
+            loop {
+                if let Some(line) = this.inner.lines.pop_front() {
+                    return Poll::Ready(Some(Ok(line)));
+                }
+                if let Some(sleep) = this.sleep.as_mut() {
+                    if sleep.as_mut().poll(cx).is_pending() {
+                        return Poll::Pending;
+                    }
+                    this.sleep = None;
+                }
+                match this.inner.poll_lines() {
+                    Ok(lines) if lines.is_empty() => {
+                        this.sleep = Some(Box::pin(tokio::time::sleep(this.inner.interval)));
+                    }
+                    Ok(lines) => this.inner.lines.extend(lines),
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                }
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[tokio::test]
+        async fn stream_yields_new_lines() {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("trace0");
+            fs::write(&path, "old\n").unwrap();
+            let mut f = AsyncTraceFollow::new(path.clone(), Duration::from_millis(1)).await.unwrap();
+            let writer = tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                fs::write(&path, "old\nnew 1\nnew 2\n").unwrap();
+            });
+            assert_eq!(f.next_line().await.unwrap(), "new 1");
+            let next = std::future::poll_fn(|cx| Pin::new(&mut f).poll_next(cx)).await;
+            assert_eq!(next.unwrap().unwrap(), "new 2");
+            writer.await.unwrap();
+        }
+    }
+}